@@ -0,0 +1,53 @@
+//! Runs a few Lox programs on the bytecode VM. Compare the value
+//! representations with
+//!
+//! ```sh
+//! cargo bench --bench vm -- --save-baseline enum
+//! cargo bench --bench vm --features nan-boxing -- --baseline enum
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use lox::Vm;
+
+const FIB: &str = "
+fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+var result = fib(20);
+";
+
+const LOOP: &str = "
+var sum = 0;
+for (var i = 0; i < 100000; i = i + 1) {
+  if (i / 2 > 10 and !(i == 5)) sum = sum + i;
+}
+";
+
+const INSTANCES: &str = "
+class Point {
+  init(x, y) { this.x = x; this.y = y; }
+  add(other) { return Point(this.x + other.x, this.y + other.y); }
+}
+var p = Point(0, 0);
+for (var i = 0; i < 20000; i = i + 1) p = p.add(Point(1, 2));
+";
+
+const STRINGS: &str = "
+var s = \"\";
+for (var i = 0; i < 2000; i = i + 1) {
+  s = \"a\" + \"b\";
+  if (s == \"ab\") s = s + \"c\";
+}
+";
+
+fn run(source: &str) {
+    Vm::new().run(source).unwrap();
+}
+
+fn benchmarks(c: &mut Criterion) {
+    c.bench_function("fib", |b| b.iter(|| run(FIB)));
+    c.bench_function("loop", |b| b.iter(|| run(LOOP)));
+    c.bench_function("instances", |b| b.iter(|| run(INSTANCES)));
+    c.bench_function("strings", |b| b.iter(|| run(STRINGS)));
+}
+
+criterion_group!(benches, benchmarks);
+criterion_main!(benches);
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::scanner::{Span, Token, TokenType};
+
+pub enum Expression {
+    Literal(Token),
+    Unary(Token, Box<Expression>),
+    Binary(Box<Expression>, Token, Box<Expression>),
+    Grouping(Box<Expression>),
+    Variable(usize, Token),
+    Assign(usize, Token, Box<Expression>),
+    Logical(Box<Expression>, Token, Box<Expression>),
+    Call(Box<Expression>, Token, Vec<Expression>),
+    Get(Box<Expression>, Token),
+    Set(Box<Expression>, Token, Box<Expression>),
+    This(usize, Token),
+    Super(usize, Token, Token),
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Literal(token)
+            | Expression::Variable(_, token)
+            | Expression::This(_, token) => token.span,
+            Expression::Unary(operator, right) => operator.span.to(right.span()),
+            Expression::Binary(left, _, right) | Expression::Logical(left, _, right) => {
+                left.span().to(right.span())
+            }
+            Expression::Grouping(expression) => expression.span(),
+            Expression::Assign(_, name, value) => name.span.to(value.span()),
+            Expression::Call(callee, paren, _) => callee.span().to(paren.span),
+            Expression::Get(object, name) => object.span().to(name.span),
+            Expression::Set(object, _, value) => object.span().to(value.span()),
+            Expression::Super(_, keyword, method) => keyword.span.to(method.span),
+        }
+    }
+
+    /// The line the expression starts on.
+    pub fn line(&self) -> usize {
+        match self {
+            Expression::Literal(token)
+            | Expression::Variable(_, token)
+            | Expression::This(_, token)
+            | Expression::Unary(token, _)
+            | Expression::Assign(_, token, _)
+            | Expression::Super(_, token, _) => token.line,
+            Expression::Binary(left, ..)
+            | Expression::Logical(left, ..)
+            | Expression::Grouping(left)
+            | Expression::Call(left, ..)
+            | Expression::Get(left, _)
+            | Expression::Set(left, ..) => left.line(),
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Literal(value) => {
+                let literal = match &value._type {
+                    TokenType::StringLiteral(value) => value.to_string(),
+                    TokenType::Number(value) => {
+                        let integer: f64 = (*value as i64) as f64;
+                        if integer.to_bits() == value.to_bits() {
+                            format!("{}.0", integer)
+                        } else {
+                            value.to_string()
+                        }
+                    }
+                    _ => value.lexeme.to_string(),
+                };
+                write!(f, "{}", literal)
+            }
+            Expression::Unary(operator, expression) => {
+                write!(f, "({} {})", operator.lexeme, expression)
+            }
+            Expression::Binary(left, operator, right) => {
+                write!(f, "({} {} {})", operator.lexeme, left, right)
+            }
+            Expression::Grouping(expression) => write!(f, "(group {})", expression),
+            Expression::Variable(_, name) => write!(f, "{}", name.lexeme),
+            Expression::Assign(_, name, value) => write!(f, "(= {} {})", name.lexeme, value),
+            Expression::Logical(left, operator, right) => {
+                write!(f, "({} {} {})", operator.lexeme, left, right)
+            }
+            Expression::Call(callee, _, arguments) => {
+                write!(f, "(call {}", callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
+            }
+            Expression::Get(object, name) => write!(f, "(get {} {})", object, name.lexeme),
+            Expression::Set(object, name, value) => {
+                write!(f, "(set {} {} {})", object, name.lexeme, value)
+            }
+            Expression::This(..) => write!(f, "this"),
+            Expression::Super(_, _, method) => write!(f, "(super {})", method.lexeme),
+        }
+    }
+}
+
+pub enum Statement {
+    Expression(Expression),
+    Print(Expression),
+    Var(Token, Option<Expression>),
+    Block(Vec<Statement>),
+    If(Expression, Box<Statement>, Option<Box<Statement>>),
+    While(Expression, Box<Statement>),
+    Function(Rc<FunctionDeclaration>),
+    Return(Token, Option<Expression>),
+    Class(Token, Option<Expression>, Vec<Rc<FunctionDeclaration>>),
+}
+
+impl Statement {
+    /// The line the statement starts on, or `None` for a block, which only
+    /// groups the statements inside it.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Statement::Expression(expression)
+            | Statement::Print(expression)
+            | Statement::If(expression, ..)
+            | Statement::While(expression, _) => Some(expression.line()),
+            Statement::Var(name, _) | Statement::Class(name, ..) => Some(name.line),
+            Statement::Function(declaration) => Some(declaration.name.line),
+            Statement::Return(keyword, _) => Some(keyword.line),
+            Statement::Block(_) => None,
+        }
+    }
+}
+
+pub struct FunctionDeclaration {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Statement>,
+}
@@ -0,0 +1,667 @@
+use std::fmt;
+
+use crate::scanner::{Scanner, Span, SyntaxToken, TokenType};
+
+/// What a [`SyntaxNode`] is. Declarations, statements and expressions mirror
+/// the grammar the [`Parser`](crate::Parser) accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+
+    ClassDeclaration,
+    /// A function or, inside a class, a method.
+    FunctionDeclaration,
+    /// The parenthesized parameter list of a function.
+    Parameters,
+    VarDeclaration,
+
+    ExpressionStatement,
+    PrintStatement,
+    ReturnStatement,
+    Block,
+    IfStatement,
+    WhileStatement,
+    ForStatement,
+
+    Literal,
+    Variable,
+    This,
+    Super,
+    Grouping,
+    Unary,
+    Binary,
+    Logical,
+    Call,
+    /// The parenthesized argument list of a call.
+    Arguments,
+    Get,
+    /// An assignment to a variable or a property.
+    Assign,
+
+    /// Tokens that don't fit the grammar where they appear, or nothing at all
+    /// where a token is missing.
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// A node of the concrete syntax tree. Unlike the [`ast`](crate::ast), it
+/// keeps every token, including punctuation, in source order, and the tokens
+/// keep their trivia, so printing a tree gives back exactly what was parsed.
+#[derive(Clone, Debug)]
+pub struct SyntaxNode {
+    kind: SyntaxKind,
+    children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind, children: Vec<SyntaxElement>) -> Self {
+        SyntaxNode { kind, children }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        return self.kind;
+    }
+
+    pub fn children(&self) -> &[SyntaxElement] {
+        return &self.children;
+    }
+
+    /// The child nodes, skipping tokens.
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        return self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        });
+    }
+
+    /// Every token under this node, in source order.
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        return tokens;
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    /// The source from the first token to the last, without the trivia
+    /// around them, or `None` if the node has no tokens.
+    pub fn span(&self) -> Option<Span> {
+        let tokens = self.tokens();
+        let first = tokens.first()?.token.span;
+        let last = tokens.last()?.token.span;
+        return Some(first.to(last));
+    }
+
+    /// An indented outline of the tree, one node or token per line, with
+    /// trivia under the token it belongs to.
+    pub fn dump(&self) -> String {
+        let mut output = String::new();
+        self.dump_into(&mut output, 0);
+        return output;
+    }
+
+    fn dump_into(&self, output: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        match self.span() {
+            Some(span) => {
+                *output += &format!("{}{:?}@{}..{}\n", indent, self.kind, span.start, span.end)
+            }
+            None => *output += &format!("{}{:?}\n", indent, self.kind),
+        }
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.dump_into(output, depth + 1),
+                SyntaxElement::Token(token) => {
+                    let span = token.token.span;
+                    *output += &format!(
+                        "{}  {}@{}..{} {:?}\n",
+                        indent, token.token._type, span.start, span.end, token.token.lexeme
+                    );
+                    for trivia in &token.leading {
+                        *output += &format!(
+                            "{}    leading {:?} {:?}\n",
+                            indent, trivia.kind, trivia.text
+                        );
+                    }
+                    for trivia in &token.trailing {
+                        *output += &format!(
+                            "{}    trailing {:?} {:?}\n",
+                            indent, trivia.kind, trivia.text
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Prints the source the node was parsed from, trivia included.
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in self.tokens() {
+            write!(f, "{}", token.full_text())?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `source` into a concrete syntax tree. This never fails: text the
+/// scanner rejects becomes trivia, and tokens the grammar doesn't expect end
+/// up in [`SyntaxKind::Error`] nodes, so the tree always prints back to
+/// `source`. Use the [`Parser`](crate::Parser) to report what's wrong.
+pub fn parse(source: &str) -> SyntaxNode {
+    let (tokens, _) = Scanner::scan_lossless(source);
+    let mut parser = CstParser {
+        tokens: tokens.into_iter().peekable(),
+        consumed: 0,
+    };
+    return parser.program();
+}
+
+/// A recursive descent parser over the grammar of [`Parser`](crate::Parser)
+/// that recovers from every error in place instead of unwinding.
+struct CstParser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<SyntaxToken>>,
+    /// How many tokens have been moved into the tree, to tell whether a rule
+    /// made progress.
+    consumed: usize,
+}
+
+type Children = Vec<SyntaxElement>;
+
+impl CstParser {
+    fn program(&mut self) -> SyntaxNode {
+        let mut children = Vec::new();
+        while !matches!(self.peek(), TokenType::Eof) {
+            self.declaration(&mut children);
+        }
+        self.bump(&mut children);
+        return SyntaxNode::new(SyntaxKind::Program, children);
+    }
+
+    /// Parses one declaration into `children`. Something that can't start
+    /// one is moved into an error node, so every call consumes a token.
+    fn declaration(&mut self, children: &mut Children) {
+        let consumed = self.consumed;
+        let declaration = match self.peek() {
+            TokenType::Class => self.class_declaration(),
+            TokenType::Fun => self.function(true),
+            TokenType::Var => self.var_declaration(),
+            _ => self.statement(),
+        };
+        self.push_or_skip(children, declaration, consumed);
+    }
+
+    /// Pushes `node` if parsing it consumed anything since `consumed`, and
+    /// otherwise moves the token it couldn't start with into an error node.
+    fn push_or_skip(&mut self, children: &mut Children, node: SyntaxNode, consumed: usize) {
+        if self.consumed > consumed {
+            children.push(SyntaxElement::Node(node));
+            return;
+        }
+        let mut skipped = Vec::new();
+        self.bump(&mut skipped);
+        children.push(SyntaxElement::Node(SyntaxNode::new(
+            SyntaxKind::Error,
+            skipped,
+        )));
+    }
+
+    fn class_declaration(&mut self) -> SyntaxNode {
+        let mut children = Vec::new();
+        self.bump(&mut children);
+        self.expect(&mut children, |t| matches!(t, TokenType::Identifier));
+        if matches!(self.peek(), TokenType::Less) {
+            self.bump(&mut children);
+            self.expect(&mut children, |t| matches!(t, TokenType::Identifier));
+        }
+        if self.expect(&mut children, |t| matches!(t, TokenType::LeftBrace)) {
+            while !matches!(self.peek(), TokenType::RightBrace | TokenType::Eof) {
+                let consumed = self.consumed;
+                let method = self.function(false);
+                self.push_or_skip(&mut children, method, consumed);
+            }
+            self.expect(&mut children, |t| matches!(t, TokenType::RightBrace));
+        }
+        return SyntaxNode::new(SyntaxKind::ClassDeclaration, children);
+    }
+
+    /// A function declaration, which starts with `fun` unless it's a method.
+    fn function(&mut self, has_keyword: bool) -> SyntaxNode {
+        let mut children = Vec::new();
+        if has_keyword {
+            self.bump(&mut children);
+        }
+        if !self.expect(&mut children, |t| matches!(t, TokenType::Identifier)) {
+            return SyntaxNode::new(SyntaxKind::FunctionDeclaration, children);
+        }
+
+        let mut parameters = Vec::new();
+        if self.expect(&mut parameters, |t| matches!(t, TokenType::LeftParen)) {
+            if !matches!(self.peek(), TokenType::RightParen) {
+                loop {
+                    self.expect(&mut parameters, |t| matches!(t, TokenType::Identifier));
+                    if !self.eat(&mut parameters, |t| matches!(t, TokenType::Comma)) {
+                        break;
+                    }
+                }
+            }
+            self.expect(&mut parameters, |t| matches!(t, TokenType::RightParen));
+        }
+        children.push(SyntaxElement::Node(SyntaxNode::new(
+            SyntaxKind::Parameters,
+            parameters,
+        )));
+
+        if matches!(self.peek(), TokenType::LeftBrace) {
+            children.push(SyntaxElement::Node(self.block()));
+        } else {
+            children.push(SyntaxElement::Node(SyntaxNode::new(
+                SyntaxKind::Error,
+                Vec::new(),
+            )));
+        }
+        return SyntaxNode::new(SyntaxKind::FunctionDeclaration, children);
+    }
+
+    fn var_declaration(&mut self) -> SyntaxNode {
+        let mut children = Vec::new();
+        self.bump(&mut children);
+        self.expect(&mut children, |t| matches!(t, TokenType::Identifier));
+        if self.eat(&mut children, |t| matches!(t, TokenType::Equal)) {
+            children.push(SyntaxElement::Node(self.expression()));
+        }
+        self.expect(&mut children, |t| matches!(t, TokenType::Semicolon));
+        return SyntaxNode::new(SyntaxKind::VarDeclaration, children);
+    }
+
+    fn statement(&mut self) -> SyntaxNode {
+        match self.peek() {
+            TokenType::For => return self.for_statement(),
+            TokenType::If => return self.if_statement(),
+            TokenType::Print => return self.keyword_statement(SyntaxKind::PrintStatement),
+            TokenType::Return => return self.keyword_statement(SyntaxKind::ReturnStatement),
+            TokenType::While => return self.while_statement(),
+            TokenType::LeftBrace => return self.block(),
+            _ => return self.expression_statement(),
+        }
+    }
+
+    /// A `for` loop. Its clauses are children in source order, told apart by
+    /// the `;` tokens between them.
+    fn for_statement(&mut self) -> SyntaxNode {
+        let mut children = Vec::new();
+        self.bump(&mut children);
+        self.expect(&mut children, |t| matches!(t, TokenType::LeftParen));
+        match self.peek() {
+            TokenType::Semicolon => self.bump(&mut children),
+            TokenType::Var => children.push(SyntaxElement::Node(self.var_declaration())),
+            _ => children.push(SyntaxElement::Node(self.expression_statement())),
+        }
+        if !matches!(self.peek(), TokenType::Semicolon) {
+            children.push(SyntaxElement::Node(self.expression()));
+        }
+        self.expect(&mut children, |t| matches!(t, TokenType::Semicolon));
+        if !matches!(self.peek(), TokenType::RightParen) {
+            children.push(SyntaxElement::Node(self.expression()));
+        }
+        self.expect(&mut children, |t| matches!(t, TokenType::RightParen));
+        children.push(SyntaxElement::Node(self.statement()));
+        return SyntaxNode::new(SyntaxKind::ForStatement, children);
+    }
+
+    fn if_statement(&mut self) -> SyntaxNode {
+        let mut children = Vec::new();
+        self.bump(&mut children);
+        self.condition(&mut children);
+        children.push(SyntaxElement::Node(self.statement()));
+        if self.eat(&mut children, |t| matches!(t, TokenType::Else)) {
+            children.push(SyntaxElement::Node(self.statement()));
+        }
+        return SyntaxNode::new(SyntaxKind::IfStatement, children);
+    }
+
+    fn while_statement(&mut self) -> SyntaxNode {
+        let mut children = Vec::new();
+        self.bump(&mut children);
+        self.condition(&mut children);
+        children.push(SyntaxElement::Node(self.statement()));
+        return SyntaxNode::new(SyntaxKind::WhileStatement, children);
+    }
+
+    /// The parenthesized condition of an `if` or `while`.
+    fn condition(&mut self, children: &mut Children) {
+        self.expect(children, |t| matches!(t, TokenType::LeftParen));
+        children.push(SyntaxElement::Node(self.expression()));
+        self.expect(children, |t| matches!(t, TokenType::RightParen));
+    }
+
+    fn block(&mut self) -> SyntaxNode {
+        let mut children = Vec::new();
+        self.bump(&mut children);
+        while !matches!(self.peek(), TokenType::RightBrace | TokenType::Eof) {
+            self.declaration(&mut children);
+        }
+        self.expect(&mut children, |t| matches!(t, TokenType::RightBrace));
+        return SyntaxNode::new(SyntaxKind::Block, children);
+    }
+
+    /// A `print` or `return` statement: the keyword, then an expression
+    /// that only `return` may leave out, then a `;`.
+    fn keyword_statement(&mut self, kind: SyntaxKind) -> SyntaxNode {
+        let mut children = Vec::new();
+        self.bump(&mut children);
+        if kind != SyntaxKind::ReturnStatement || !matches!(self.peek(), TokenType::Semicolon) {
+            children.push(SyntaxElement::Node(self.expression()));
+        }
+        self.expect(&mut children, |t| matches!(t, TokenType::Semicolon));
+        return SyntaxNode::new(kind, children);
+    }
+
+    fn expression_statement(&mut self) -> SyntaxNode {
+        let mut children = vec![SyntaxElement::Node(self.expression())];
+        self.expect(&mut children, |t| matches!(t, TokenType::Semicolon));
+        return SyntaxNode::new(SyntaxKind::ExpressionStatement, children);
+    }
+
+    fn expression(&mut self) -> SyntaxNode {
+        return self.assignment();
+    }
+
+    /// An assignment keeps whatever is left of the `=` as its target; the
+    /// parser is the one to reject targets that can't be assigned.
+    fn assignment(&mut self) -> SyntaxNode {
+        let target = self.or();
+        if !matches!(self.peek(), TokenType::Equal) {
+            return target;
+        }
+        let mut children = vec![SyntaxElement::Node(target)];
+        self.bump(&mut children);
+        children.push(SyntaxElement::Node(self.assignment()));
+        return SyntaxNode::new(SyntaxKind::Assign, children);
+    }
+
+    fn or(&mut self) -> SyntaxNode {
+        return self.binary(SyntaxKind::Logical, CstParser::and, |t| {
+            matches!(t, TokenType::Or)
+        });
+    }
+
+    fn and(&mut self) -> SyntaxNode {
+        return self.binary(SyntaxKind::Logical, CstParser::equality, |t| {
+            matches!(t, TokenType::And)
+        });
+    }
+
+    fn equality(&mut self) -> SyntaxNode {
+        return self.binary(SyntaxKind::Binary, CstParser::comparison, |t| {
+            matches!(t, TokenType::BangEqual | TokenType::EqualEqual)
+        });
+    }
+
+    fn comparison(&mut self) -> SyntaxNode {
+        return self.binary(SyntaxKind::Binary, CstParser::term, |t| {
+            matches!(
+                t,
+                TokenType::Greater
+                    | TokenType::GreaterEqual
+                    | TokenType::Less
+                    | TokenType::LessEqual
+            )
+        });
+    }
+
+    fn term(&mut self) -> SyntaxNode {
+        return self.binary(SyntaxKind::Binary, CstParser::factor, |t| {
+            matches!(t, TokenType::Minus | TokenType::Plus)
+        });
+    }
+
+    fn factor(&mut self) -> SyntaxNode {
+        return self.binary(SyntaxKind::Binary, CstParser::unary, |t| {
+            matches!(t, TokenType::Slash | TokenType::Star)
+        });
+    }
+
+    /// A left-associative chain of `operand`s joined by operators.
+    fn binary(
+        &mut self,
+        kind: SyntaxKind,
+        operand: fn(&mut CstParser) -> SyntaxNode,
+        is_operator: fn(&TokenType) -> bool,
+    ) -> SyntaxNode {
+        let mut left = operand(self);
+        while is_operator(self.peek()) {
+            let mut children = vec![SyntaxElement::Node(left)];
+            self.bump(&mut children);
+            children.push(SyntaxElement::Node(operand(self)));
+            left = SyntaxNode::new(kind, children);
+        }
+        return left;
+    }
+
+    fn unary(&mut self) -> SyntaxNode {
+        if !matches!(self.peek(), TokenType::Bang | TokenType::Minus) {
+            return self.call();
+        }
+        let mut children = Vec::new();
+        self.bump(&mut children);
+        children.push(SyntaxElement::Node(self.unary()));
+        return SyntaxNode::new(SyntaxKind::Unary, children);
+    }
+
+    fn call(&mut self) -> SyntaxNode {
+        let mut expression = self.primary();
+        loop {
+            let mut children = vec![SyntaxElement::Node(expression)];
+            if matches!(self.peek(), TokenType::LeftParen) {
+                let mut arguments = Vec::new();
+                self.bump(&mut arguments);
+                if !matches!(self.peek(), TokenType::RightParen) {
+                    loop {
+                        arguments.push(SyntaxElement::Node(self.expression()));
+                        if !self.eat(&mut arguments, |t| matches!(t, TokenType::Comma)) {
+                            break;
+                        }
+                    }
+                }
+                self.expect(&mut arguments, |t| matches!(t, TokenType::RightParen));
+                children.push(SyntaxElement::Node(SyntaxNode::new(
+                    SyntaxKind::Arguments,
+                    arguments,
+                )));
+                expression = SyntaxNode::new(SyntaxKind::Call, children);
+            } else if matches!(self.peek(), TokenType::Dot) {
+                self.bump(&mut children);
+                self.expect(&mut children, |t| matches!(t, TokenType::Identifier));
+                expression = SyntaxNode::new(SyntaxKind::Get, children);
+            } else {
+                let Some(SyntaxElement::Node(expression)) = children.pop() else {
+                    unreachable!("the expression was pushed first");
+                };
+                return expression;
+            }
+        }
+    }
+
+    fn primary(&mut self) -> SyntaxNode {
+        let mut children = Vec::new();
+        let kind = match self.peek() {
+            TokenType::False
+            | TokenType::True
+            | TokenType::Nil
+            | TokenType::Number(_)
+            | TokenType::StringLiteral(_) => SyntaxKind::Literal,
+            TokenType::Identifier => SyntaxKind::Variable,
+            TokenType::This => SyntaxKind::This,
+            TokenType::Super => {
+                self.bump(&mut children);
+                self.expect(&mut children, |t| matches!(t, TokenType::Dot));
+                self.expect(&mut children, |t| matches!(t, TokenType::Identifier));
+                return SyntaxNode::new(SyntaxKind::Super, children);
+            }
+            TokenType::LeftParen => {
+                self.bump(&mut children);
+                children.push(SyntaxElement::Node(self.expression()));
+                self.expect(&mut children, |t| matches!(t, TokenType::RightParen));
+                return SyntaxNode::new(SyntaxKind::Grouping, children);
+            }
+            // Leave the tokens that close whatever encloses the expression,
+            // so the missing expression doesn't take its surroundings along.
+            TokenType::Semicolon
+            | TokenType::RightParen
+            | TokenType::RightBrace
+            | TokenType::Eof => {
+                return SyntaxNode::new(SyntaxKind::Error, children);
+            }
+            _ => SyntaxKind::Error,
+        };
+        self.bump(&mut children);
+        return SyntaxNode::new(kind, children);
+    }
+
+    fn peek(&mut self) -> &TokenType {
+        return &self
+            .tokens
+            .peek()
+            .expect("the end of file token is never consumed early")
+            .token
+            ._type;
+    }
+
+    /// Moves the next token into `children`.
+    fn bump(&mut self, children: &mut Children) {
+        let token = self
+            .tokens
+            .next()
+            .expect("the end of file token is never consumed early");
+        children.push(SyntaxElement::Token(token));
+        self.consumed += 1;
+    }
+
+    /// Moves the next token into `children` if it's what `check` accepts.
+    fn eat(&mut self, children: &mut Children, check: fn(&TokenType) -> bool) -> bool {
+        if !check(self.peek()) {
+            return false;
+        }
+        self.bump(children);
+        return true;
+    }
+
+    /// Like [`CstParser::eat`], but marks a missing token with an empty error
+    /// node.
+    fn expect(&mut self, children: &mut Children, check: fn(&TokenType) -> bool) -> bool {
+        if self.eat(children, check) {
+            return true;
+        }
+        children.push(SyntaxElement::Node(SyntaxNode::new(
+            SyntaxKind::Error,
+            Vec::new(),
+        )));
+        return false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = r#"
+// A counter.
+class Counter < Base {
+  init() { this.count = 0; } // Starts at zero.
+  add(n) {
+    this.count = this.count + n;
+    return this;
+  }
+}
+
+fun count(limit) {
+  var counter = Counter();
+  for (var i = 0; i < limit; i = i + 1) {
+    if (i / 2 == 1 or !true) counter.add(i); else print "odd";
+  }
+  while (false) {}
+  return counter.count;
+}
+print count(10);
+"#;
+
+    fn assert_round_trips(source: &str) {
+        assert_eq!(parse(source).to_string(), source, "{:?}", source);
+    }
+
+    fn has_error(node: &SyntaxNode) -> bool {
+        return matches!(node.kind(), SyntaxKind::Error) || node.nodes().any(has_error);
+    }
+
+    #[test]
+    fn round_trips_valid_programs() {
+        assert_round_trips(PROGRAM);
+        assert_round_trips("");
+        assert_round_trips("print 1;");
+        assert!(!has_error(&parse(PROGRAM)));
+    }
+
+    #[test]
+    fn round_trips_comments_and_whitespace() {
+        let sources = [
+            "// only a comment",
+            "// only a comment\n",
+            "\n\n   \t\n",
+            "print 1; // trailing\n// own line\nprint 2;",
+            "print /* not a block comment */ 1;",
+            "print\t1\t;\t\t\n",
+            "print 1;\r\nprint 2;\r\n",
+            "  {  var  a  =  1  ;  }  ",
+            "print (\n1\n+\n// between\n2\n)\n;",
+            "print 1;   \n\n\n   ",
+        ];
+        for source in sources {
+            assert_round_trips(source);
+        }
+    }
+
+    #[test]
+    fn round_trips_invalid_source() {
+        let sources = [
+            "print \"unterminated",
+            "print \"unterminated\nover lines;\n",
+            "var a = \"",
+            "print @;",
+            "var # = 1;\nprint $ + 2;",
+            "print \"é\" + €;",
+            "print 1 +;",
+            "fun (a, { return; ",
+            "class { } } ) ;",
+            "if (",
+            "}}}",
+            "var 1 = 2; print;",
+            "for (;;",
+            "a.b.c = = 1;",
+        ];
+        for source in sources {
+            assert_round_trips(source);
+            assert!(has_error(&parse(source)), "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn round_trips_every_prefix() {
+        for (end, _) in PROGRAM.char_indices() {
+            assert_round_trips(&PROGRAM[..end]);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Step debugging for the tree-walking [`Interpreter`], driven either from
+//! the terminal ([`console`]) or by an editor over the Debug Adapter
+//! Protocol ([`dap`]).
+
+pub mod console;
+pub mod dap;
+
+use std::collections::HashSet;
+
+use crate::interpreter::{Interpreter, Object};
+
+/// How to carry on from a pause.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resume {
+    /// Run until a breakpoint.
+    Continue,
+    /// Pause at the very next statement, even inside a call.
+    StepIn,
+    /// Pause at the next statement that isn't inside a call it makes.
+    StepOver,
+    /// Pause at the next statement after the current call returns.
+    StepOut,
+}
+
+/// Why the program paused.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reason {
+    Entry,
+    Breakpoint,
+    Step,
+    /// The client asked for a pause while the program ran.
+    Pause,
+}
+
+/// Decides which statements to pause at, from the breakpoints and the last
+/// way the program was resumed. Frontends ask it before every statement and
+/// tell it how the user chose to carry on.
+pub struct Stepper {
+    breakpoints: HashSet<usize>,
+    resume: Resume,
+    /// How deep the call stack was when the program was last resumed.
+    depth: usize,
+    /// The line of the previous statement. A breakpoint only fires when the
+    /// program arrives on its line, not again for each statement on it.
+    line: usize,
+    stop_on_entry: bool,
+    pause_requested: bool,
+}
+
+impl Stepper {
+    /// Runs until a breakpoint.
+    pub fn new() -> Self {
+        Stepper {
+            breakpoints: HashSet::new(),
+            resume: Resume::Continue,
+            depth: 0,
+            line: 0,
+            stop_on_entry: false,
+            pause_requested: false,
+        }
+    }
+
+    /// Pauses at the first statement, before any breakpoint.
+    pub fn stop_on_entry(&mut self) {
+        self.stop_on_entry = true;
+    }
+
+    pub fn breakpoints(&self) -> &HashSet<usize> {
+        return &self.breakpoints;
+    }
+
+    /// Sets a breakpoint on `line`, returning false if there already was one.
+    pub fn add_breakpoint(&mut self, line: usize) -> bool {
+        return self.breakpoints.insert(line);
+    }
+
+    pub fn remove_breakpoint(&mut self, line: usize) -> bool {
+        return self.breakpoints.remove(&line);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Pauses before the next statement, whatever it is.
+    pub fn request_pause(&mut self) {
+        self.pause_requested = true;
+    }
+
+    /// Whether to pause before the statement on `line`, and why.
+    pub fn check(&mut self, interpreter: &Interpreter, line: usize) -> Option<Reason> {
+        let depth = interpreter.call_depth();
+        let arrived = line != self.line;
+        self.line = line;
+
+        if self.stop_on_entry {
+            self.stop_on_entry = false;
+            return Some(Reason::Entry);
+        }
+        if self.pause_requested {
+            self.pause_requested = false;
+            return Some(Reason::Pause);
+        }
+        let stepped = match self.resume {
+            Resume::Continue => false,
+            Resume::StepIn => true,
+            Resume::StepOver => depth <= self.depth,
+            Resume::StepOut => depth < self.depth,
+        };
+        if stepped {
+            return Some(Reason::Step);
+        }
+        if arrived && self.breakpoints.contains(&line) {
+            return Some(Reason::Breakpoint);
+        }
+        return None;
+    }
+
+    /// Carries on from a pause at the current statement.
+    pub fn resume(&mut self, interpreter: &Interpreter, resume: Resume) {
+        self.resume = resume;
+        self.depth = interpreter.call_depth();
+    }
+}
+
+impl Default for Stepper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The value of the variable `name` as a frame of the call stack sees it.
+fn lookup(interpreter: &Interpreter, frame: usize, name: &str) -> Option<Object> {
+    return interpreter
+        .locals(frame)
+        .into_iter()
+        .chain(interpreter.globals())
+        .find(|(symbol, _)| symbol == name)
+        .map(|(_, value)| value);
+}
+
+/// A value as the debugger shows it: like `print`, but with strings quoted
+/// so they can't be mistaken for other values.
+fn describe(value: &Object) -> String {
+    match value {
+        Object::String(text) => return format!("{:?}", text.as_str()),
+        _ => return value.to_string(),
+    }
+}
+
+fn reason_name(reason: Reason) -> &'static str {
+    match reason {
+        Reason::Entry => return "entry",
+        Reason::Breakpoint => return "breakpoint",
+        Reason::Step => return "step",
+        Reason::Pause => return "pause",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::ops::ControlFlow;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::interpreter::DebugHook;
+
+    const CALLS: &str = "fun inner() {
+  print \"inner\";
+}
+fun outer() {
+  inner();
+  print \"outer\";
+}
+outer();
+print \"done\";
+";
+
+    /// A frontend that resumes each pause with the next of `resumes`,
+    /// continuing once they run out, and requests a pause whenever the
+    /// program prints `"pause"`.
+    struct Script {
+        stepper: Stepper,
+        resumes: VecDeque<Resume>,
+        stops: Rc<RefCell<Vec<(usize, Reason)>>>,
+    }
+
+    impl DebugHook for Script {
+        fn before_statement(&mut self, interpreter: &Interpreter, line: usize) -> ControlFlow<()> {
+            if let Some(reason) = self.stepper.check(interpreter, line) {
+                self.stops.borrow_mut().push((line, reason));
+                let resume = self.resumes.pop_front().unwrap_or(Resume::Continue);
+                self.stepper.resume(interpreter, resume);
+            }
+            return ControlFlow::Continue(());
+        }
+
+        fn output(&mut self, text: &str) {
+            if text == "pause\n" {
+                self.stepper.request_pause();
+            }
+        }
+    }
+
+    /// The lines the program paused on and why.
+    fn stops(source: &str, stepper: Stepper, resumes: &[Resume]) -> Vec<(usize, Reason)> {
+        let stops = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_debug_hook(Box::new(Script {
+            stepper,
+            resumes: resumes.iter().copied().collect(),
+            stops: Rc::clone(&stops),
+        }));
+        interpreter.run(source).unwrap();
+        return stops.take();
+    }
+
+    fn breakpoints(lines: &[usize]) -> Stepper {
+        let mut stepper = Stepper::new();
+        for &line in lines {
+            stepper.add_breakpoint(line);
+        }
+        return stepper;
+    }
+
+    #[test]
+    fn step_over_stays_in_the_current_call_or_its_callers() {
+        let resumes = [Resume::StepOver, Resume::StepOver, Resume::StepOver];
+        assert_eq!(
+            stops(CALLS, breakpoints(&[5]), &resumes),
+            [
+                (5, Reason::Breakpoint),
+                (6, Reason::Step),
+                (9, Reason::Step),
+            ]
+        );
+    }
+
+    #[test]
+    fn step_in_enters_calls_and_step_out_leaves_them() {
+        let resumes = [Resume::StepIn, Resume::StepOut, Resume::StepOut];
+        assert_eq!(
+            stops(CALLS, breakpoints(&[5]), &resumes),
+            [
+                (5, Reason::Breakpoint),
+                (2, Reason::Step),
+                (6, Reason::Step),
+                (9, Reason::Step),
+            ]
+        );
+    }
+
+    #[test]
+    fn breakpoints_fire_once_each_time_their_line_is_reached() {
+        let source = "var i = 0;
+while (i < 2) {
+  print i; print i;
+  i = i + 1;
+}
+";
+        assert_eq!(
+            stops(source, breakpoints(&[3]), &[]),
+            [(3, Reason::Breakpoint), (3, Reason::Breakpoint)]
+        );
+    }
+
+    #[test]
+    fn stopping_on_entry_comes_before_breakpoints() {
+        let mut stepper = breakpoints(&[1, 2]);
+        stepper.stop_on_entry();
+        assert_eq!(
+            stops("print 1;\nprint 2;\n", stepper, &[]),
+            [(1, Reason::Entry), (2, Reason::Breakpoint)]
+        );
+    }
+
+    #[test]
+    fn pauses_at_the_next_statement_when_asked() {
+        let source = "print 1;\nprint \"pause\";\nprint 3;\nprint 4;\n";
+        assert_eq!(stops(source, Stepper::new(), &[]), [(3, Reason::Pause)]);
+    }
+}
@@ -0,0 +1,192 @@
+use std::io::{self, BufRead, Write};
+use std::ops::ControlFlow;
+
+use super::{describe, lookup, reason_name, Resume, Stepper};
+use crate::intern::Symbol;
+use crate::interpreter::{DebugHook, Interpreter, Object, RuntimeError};
+use crate::LoxError;
+
+const HELP: &str = "\
+Commands:
+  c, continue        run until the next breakpoint
+  s, step            step into the next statement
+  n, next            step over calls to the next statement
+  o, out, finish     step out of the current call
+  b, break LINE      set a breakpoint on LINE
+  d, delete LINE     remove the breakpoint on LINE
+  bt, backtrace      show the call stack
+  l, locals [FRAME]  show the local variables of a frame, 0 being innermost
+  g, globals         show the global variables
+  p, print NAME      show the variable NAME as the current frame sees it
+  list               show the source around the current line
+  q, quit            stop the program
+An empty line repeats the last command.";
+
+/// Runs `source` under a debugger driven from the terminal, pausing before
+/// the first statement. The debugger talks on stderr and reads commands
+/// from stdin, leaving stdout to the program; once stdin runs out the
+/// program runs to the end without pausing again. Quitting ends the program
+/// early without an error.
+pub fn run(source: &str, optimize: bool) -> Result<(), LoxError> {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_optimize(optimize);
+    let mut stepper = Stepper::new();
+    stepper.stop_on_entry();
+    interpreter.set_debug_hook(Box::new(Console {
+        stepper,
+        lines: source.lines().map(str::to_string).collect(),
+        last_command: String::new(),
+        detached: false,
+    }));
+    match interpreter.run(source) {
+        Err(LoxError::Runtime(RuntimeError::Stopped { .. })) => return Ok(()),
+        result => return result,
+    }
+}
+
+enum Command {
+    Resume(Resume),
+    Quit,
+}
+
+struct Console {
+    stepper: Stepper,
+    lines: Vec<String>,
+    last_command: String,
+    /// Set once stdin is closed, after which nothing pauses.
+    detached: bool,
+}
+
+impl DebugHook for Console {
+    fn before_statement(&mut self, interpreter: &Interpreter, line: usize) -> ControlFlow<()> {
+        if self.detached {
+            return ControlFlow::Continue(());
+        }
+        let Some(reason) = self.stepper.check(interpreter, line) else {
+            return ControlFlow::Continue(());
+        };
+        let function = &interpreter.call_stack()[0].name;
+        eprintln!(
+            "Paused at line {} in {} ({})",
+            line,
+            function,
+            reason_name(reason)
+        );
+        self.show_lines(line, 0);
+
+        let stdin = io::stdin();
+        loop {
+            eprint!("(debug) ");
+            io::stderr().flush().ok();
+            let mut input = String::new();
+            if stdin.lock().read_line(&mut input).unwrap_or(0) == 0 {
+                eprintln!();
+                self.detached = true;
+                return ControlFlow::Continue(());
+            }
+            let mut input = input.trim().to_string();
+            if input.is_empty() {
+                input = self.last_command.clone();
+            }
+            self.last_command = input.clone();
+            match self.command(interpreter, line, &input) {
+                Some(Command::Resume(resume)) => {
+                    self.stepper.resume(interpreter, resume);
+                    return ControlFlow::Continue(());
+                }
+                Some(Command::Quit) => return ControlFlow::Break(()),
+                None => {}
+            }
+        }
+    }
+}
+
+impl Console {
+    /// Runs one command, returning what to do with the program if it's to
+    /// stop waiting for commands.
+    fn command(&mut self, interpreter: &Interpreter, line: usize, input: &str) -> Option<Command> {
+        let mut words = input.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let argument = words.next();
+        match command {
+            "" => {}
+            "c" | "continue" => return Some(Command::Resume(Resume::Continue)),
+            "s" | "step" => return Some(Command::Resume(Resume::StepIn)),
+            "n" | "next" => return Some(Command::Resume(Resume::StepOver)),
+            "o" | "out" | "finish" => return Some(Command::Resume(Resume::StepOut)),
+            "b" | "break" => match argument.and_then(|line| line.parse().ok()) {
+                Some(line) => {
+                    self.stepper.add_breakpoint(line);
+                    eprintln!("Breakpoint on line {}.", line);
+                }
+                None => eprintln!("Usage: break LINE"),
+            },
+            "d" | "delete" => match argument.and_then(|line| line.parse().ok()) {
+                Some(line) if self.stepper.remove_breakpoint(line) => {
+                    eprintln!("Removed the breakpoint on line {}.", line);
+                }
+                Some(line) => eprintln!("No breakpoint on line {}.", line),
+                None => eprintln!("Usage: delete LINE"),
+            },
+            "bt" | "backtrace" => {
+                for (index, frame) in interpreter.call_stack().iter().enumerate() {
+                    eprintln!("#{} {} at line {}", index, frame.name, frame.line);
+                }
+            }
+            "l" | "locals" => {
+                let frame = argument.and_then(|frame| frame.parse().ok()).unwrap_or(0);
+                if frame >= interpreter.call_depth() {
+                    eprintln!("No frame {}.", frame);
+                    return None;
+                }
+                show_variables(interpreter.locals(frame));
+            }
+            "g" | "globals" => show_variables(interpreter.globals()),
+            "p" | "print" => {
+                let Some(name) = argument else {
+                    eprintln!("Usage: print NAME");
+                    return None;
+                };
+                match lookup(interpreter, 0, name) {
+                    Some(value) => eprintln!("{} = {}", name, describe(&value)),
+                    None => eprintln!("No variable '{}' here.", name),
+                }
+            }
+            "list" => self.show_lines(line, 3),
+            "q" | "quit" => return Some(Command::Quit),
+            "h" | "help" => eprintln!("{}", HELP),
+            _ => eprintln!("Unknown command '{}'. Type 'help' for a list.", command),
+        }
+        return None;
+    }
+
+    /// Shows the source lines within `context` of `line`, marking it.
+    fn show_lines(&self, line: usize, context: usize) {
+        let first = line.saturating_sub(context).max(1);
+        let last = (line + context).min(self.lines.len());
+        for number in first..=last {
+            let marker = if number == line { ">" } else { " " };
+            let breakpoint = if self.stepper.breakpoints().contains(&number) {
+                "*"
+            } else {
+                " "
+            };
+            eprintln!(
+                "{}{}{:>4} | {}",
+                marker,
+                breakpoint,
+                number,
+                self.lines[number - 1]
+            );
+        }
+    }
+}
+
+fn show_variables(variables: Vec<(Symbol, Object)>) {
+    if variables.is_empty() {
+        eprintln!("(none)");
+    }
+    for (name, value) in variables {
+        eprintln!("  {} = {}", name, describe(&value));
+    }
+}
@@ -0,0 +1,565 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use super::{describe, lookup, reason_name, Resume, Stepper};
+use crate::diagnostic;
+use crate::interpreter::{DebugHook, Interpreter};
+use crate::protocol::{read_message, write_message};
+
+/// Lox runs on a single thread, which the protocol still wants an id for.
+const THREAD: i64 = 1;
+
+/// The `variablesReference` of the globals. Frame `n` of the call stack
+/// has its locals at `n + 2`, since 0 means a value has no children.
+const GLOBALS: i64 = 1;
+
+/// Serves one debug session of the Debug Adapter Protocol over `input` and
+/// `output`: the client launches a program, sets its breakpoints, and the
+/// program runs until it ends or the client disconnects. What the program
+/// prints is sent to the client as `output` events.
+pub fn serve(
+    input: impl BufRead + Send + 'static,
+    output: impl Write + 'static,
+) -> io::Result<i32> {
+    // Requests such as `pause` have to get through while the program runs,
+    // so they're read on a thread of their own.
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = input;
+        while let Ok(Some(body)) = read_message(&mut input) {
+            if sender.send(body).is_err() {
+                return;
+            }
+        }
+    });
+    let adapter = Rc::new(RefCell::new(Adapter {
+        output: Box::new(output),
+        seq: 0,
+        requests,
+        stepper: Stepper::new(),
+        program: None,
+        ended: None,
+    }));
+
+    loop {
+        let mut adapter = adapter.borrow_mut();
+        let Some(request) = adapter.receive() else {
+            return Ok(1);
+        };
+        match adapter.handle(&request, None)? {
+            Control::Run => break,
+            Control::Exit => return Ok(0),
+            Control::Handled | Control::Resume(_) => {}
+        }
+    }
+
+    let source = adapter
+        .borrow()
+        .program
+        .as_ref()
+        .map(|program| program.source.clone());
+    if let Some(source) = source {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_debug_hook(Box::new(Hook(Rc::clone(&adapter))));
+        let result = interpreter.run(&source);
+        let mut adapter = adapter.borrow_mut();
+        if let Some(ended) = adapter.ended.take() {
+            return ended;
+        }
+        let code = match result {
+            Ok(()) => 0,
+            Err(error) => {
+                let output = diagnostic::render(&source, &error);
+                adapter.event("output", json!({ "category": "stderr", "output": output }))?;
+                error.exit_code()
+            }
+        };
+        adapter.event("exited", json!({ "exitCode": code }))?;
+    }
+    adapter.borrow_mut().event("terminated", json!({}))?;
+
+    loop {
+        let mut adapter = adapter.borrow_mut();
+        let Some(request) = adapter.receive() else {
+            return Ok(0);
+        };
+        if let Control::Exit = adapter.handle(&request, None)? {
+            return Ok(0);
+        }
+    }
+}
+
+/// The file being debugged.
+struct Program {
+    path: String,
+    source: String,
+}
+
+/// What the caller of [`Adapter::handle`] should do next.
+enum Control {
+    Handled,
+    /// Configuration is done, so the program can start.
+    Run,
+    Resume(Resume),
+    /// The client is done with the session.
+    Exit,
+}
+
+struct Adapter {
+    output: Box<dyn Write>,
+    /// The sequence number of the last message sent.
+    seq: u64,
+    requests: Receiver<Vec<u8>>,
+    stepper: Stepper,
+    program: Option<Program>,
+    /// How [`serve`] returns once the session ended while the program ran,
+    /// which stops the program.
+    ended: Option<io::Result<i32>>,
+}
+
+impl Adapter {
+    /// Waits for the next request, or `None` once the client is gone.
+    /// Messages that aren't JSON can't be answered and are dropped.
+    fn receive(&mut self) -> Option<Value> {
+        loop {
+            let body = self.requests.recv().ok()?;
+            if let Ok(request) = serde_json::from_slice(&body) {
+                return Some(request);
+            }
+        }
+    }
+
+    /// Answers a request. `interpreter` is the program while it's paused,
+    /// and `None` before it starts or after it ends.
+    fn handle(
+        &mut self,
+        request: &Value,
+        interpreter: Option<&Interpreter>,
+    ) -> io::Result<Control> {
+        if request["type"] != "request" {
+            return Ok(Control::Handled);
+        }
+        let arguments = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or_default();
+        let mut control = Control::Handled;
+        let body = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsEvaluateForHovers": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => Ok(self.set_breakpoints(arguments)),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                control = Control::Run;
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD, "name": "main" }] })),
+            "stackTrace" => Ok(self.stack_trace(interpreter)),
+            "scopes" => Ok(scopes(arguments)),
+            "variables" => Ok(variables(interpreter, arguments)),
+            "evaluate" => evaluate(interpreter, arguments),
+            "continue" => {
+                control = Control::Resume(Resume::Continue);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => {
+                control = Control::Resume(Resume::StepOver);
+                Ok(json!({}))
+            }
+            "stepIn" => {
+                control = Control::Resume(Resume::StepIn);
+                Ok(json!({}))
+            }
+            "stepOut" => {
+                control = Control::Resume(Resume::StepOut);
+                Ok(json!({}))
+            }
+            "pause" => {
+                self.stepper.request_pause();
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                control = Control::Exit;
+                Ok(json!({}))
+            }
+            _ => Err(format!("Unsupported request '{}'", command)),
+        };
+        self.respond(request, body)?;
+        if command == "initialize" {
+            self.event("initialized", json!({}))?;
+        }
+        return Ok(control);
+    }
+
+    /// Runs before each statement of the program, pausing it for as long as
+    /// the stepper says to, and stopping it if the session ends.
+    fn before_statement(&mut self, interpreter: &Interpreter, line: usize) -> ControlFlow<()> {
+        if self.ended.is_none() {
+            match self.pause(interpreter, line) {
+                Ok(None) => {}
+                Ok(Some(code)) => self.ended = Some(Ok(code)),
+                Err(error) => self.ended = Some(Err(error)),
+            }
+        }
+        match self.ended {
+            Some(_) => return ControlFlow::Break(()),
+            None => return ControlFlow::Continue(()),
+        }
+    }
+
+    /// Handles whatever requests came in and pauses if it's time to,
+    /// returning the adapter's exit code if the session ends meanwhile.
+    fn pause(&mut self, interpreter: &Interpreter, line: usize) -> io::Result<Option<i32>> {
+        while let Ok(body) = self.requests.try_recv() {
+            let Ok(request) = serde_json::from_slice(&body) else {
+                continue;
+            };
+            // Only a paused program can be resumed.
+            if let Control::Exit = self.handle(&request, Some(interpreter))? {
+                return Ok(Some(0));
+            }
+        }
+        let Some(reason) = self.stepper.check(interpreter, line) else {
+            return Ok(None);
+        };
+        self.event(
+            "stopped",
+            json!({
+                "reason": reason_name(reason),
+                "threadId": THREAD,
+                "allThreadsStopped": true,
+            }),
+        )?;
+        loop {
+            let Some(request) = self.receive() else {
+                return Ok(Some(1));
+            };
+            match self.handle(&request, Some(interpreter))? {
+                Control::Resume(resume) => {
+                    self.stepper.resume(interpreter, resume);
+                    return Ok(None);
+                }
+                Control::Exit => return Ok(Some(0)),
+                Control::Handled | Control::Run => {}
+            }
+        }
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let Some(path) = arguments["program"].as_str() else {
+            return Err("Launching needs the path of a 'program' to debug".to_string());
+        };
+        let source = fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read file {}: {}", path, error))?;
+        if arguments["stopOnEntry"].as_bool().unwrap_or(false) {
+            self.stepper.stop_on_entry();
+        }
+        self.program = Some(Program {
+            path: path.to_string(),
+            source,
+        });
+        return Ok(json!({}));
+    }
+
+    /// Replaces the breakpoints of the program. Breakpoints in any other
+    /// file are never hit, so they're left unverified.
+    fn set_breakpoints(&mut self, arguments: &Value) -> Value {
+        let lines: Vec<u64> = arguments["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                breakpoints
+                    .iter()
+                    .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let ours = match (&self.program, arguments["source"]["path"].as_str()) {
+            (Some(program), Some(path)) => same_file(&program.path, path),
+            _ => true,
+        };
+        if ours {
+            self.stepper.clear_breakpoints();
+        }
+        let breakpoints: Vec<Value> = lines
+            .into_iter()
+            .map(|line| {
+                if ours {
+                    self.stepper.add_breakpoint(line as usize);
+                }
+                json!({ "verified": ours, "line": line })
+            })
+            .collect();
+        return json!({ "breakpoints": breakpoints });
+    }
+
+    fn stack_trace(&self, interpreter: Option<&Interpreter>) -> Value {
+        let source = self.program.as_ref().map(|program| {
+            let name = Path::new(&program.path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string());
+            json!({ "name": name, "path": program.path })
+        });
+        let frames: Vec<Value> = interpreter
+            .map(Interpreter::call_stack)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(id, frame)| {
+                json!({
+                    "id": id,
+                    "name": frame.name,
+                    "line": frame.line,
+                    "column": 1,
+                    "source": source,
+                })
+            })
+            .collect();
+        return json!({ "totalFrames": frames.len(), "stackFrames": frames });
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        return self.send(response);
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        return self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        return write_message(&mut self.output, &message);
+    }
+}
+
+/// Installs the adapter in the interpreter, while [`serve`] keeps a handle
+/// on it for after the program ends.
+struct Hook(Rc<RefCell<Adapter>>);
+
+impl DebugHook for Hook {
+    fn before_statement(&mut self, interpreter: &Interpreter, line: usize) -> ControlFlow<()> {
+        return self.0.borrow_mut().before_statement(interpreter, line);
+    }
+
+    fn output(&mut self, text: &str) {
+        let mut adapter = self.0.borrow_mut();
+        let body = json!({ "category": "stdout", "output": text });
+        // The program stops at its next statement once the client can't be
+        // reached.
+        if let Err(error) = adapter.event("output", body) {
+            adapter.ended.get_or_insert(Err(error));
+        }
+    }
+}
+
+fn scopes(arguments: &Value) -> Value {
+    let frame = arguments["frameId"].as_i64().unwrap_or(0);
+    return json!({
+        "scopes": [
+            { "name": "Locals", "variablesReference": frame + 2, "expensive": false },
+            { "name": "Globals", "variablesReference": GLOBALS, "expensive": false },
+        ],
+    });
+}
+
+fn variables(interpreter: Option<&Interpreter>, arguments: &Value) -> Value {
+    let reference = arguments["variablesReference"].as_i64().unwrap_or(0);
+    let values = match interpreter {
+        Some(interpreter) if reference == GLOBALS => interpreter.globals(),
+        Some(interpreter) if reference > GLOBALS => interpreter.locals((reference - 2) as usize),
+        _ => Vec::new(),
+    };
+    let variables: Vec<Value> = values
+        .iter()
+        .map(|(name, value)| {
+            json!({ "name": name.as_str(), "value": describe(value), "variablesReference": 0 })
+        })
+        .collect();
+    return json!({ "variables": variables });
+}
+
+/// Evaluates a variable name, which is all the debugger understands.
+fn evaluate(interpreter: Option<&Interpreter>, arguments: &Value) -> Result<Value, String> {
+    let Some(interpreter) = interpreter else {
+        return Err("The program isn't paused".to_string());
+    };
+    let name = arguments["expression"].as_str().unwrap_or_default().trim();
+    let frame = arguments["frameId"].as_u64().unwrap_or(0) as usize;
+    match lookup(interpreter, frame, name) {
+        Some(value) => return Ok(json!({ "result": describe(&value), "variablesReference": 0 })),
+        None => return Err(format!("No variable '{}' here", name)),
+    }
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => return a == b,
+        _ => return a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Read};
+    use std::sync::mpsc::Sender;
+
+    use super::*;
+
+    /// One end of an in-memory pipe, so the test can answer the adapter as
+    /// a client would.
+    struct Pipe {
+        chunks: Receiver<Vec<u8>>,
+        chunk: io::Cursor<Vec<u8>>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.chunk.position() as usize == self.chunk.get_ref().len() {
+                match self.chunks.recv() {
+                    Ok(chunk) => self.chunk = io::Cursor::new(chunk),
+                    Err(_) => return Ok(0),
+                }
+            }
+            return self.chunk.read(buffer);
+        }
+    }
+
+    struct Sink(Sender<Vec<u8>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.0
+                .send(buffer.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            return Ok(buffer.len());
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    fn pipe() -> (Sink, BufReader<Pipe>) {
+        let (sender, chunks) = mpsc::channel();
+        let chunk = io::Cursor::new(Vec::new());
+        return (Sink(sender), BufReader::new(Pipe { chunks, chunk }));
+    }
+
+    struct Client {
+        requests: Sink,
+        messages: BufReader<Pipe>,
+        seq: u64,
+    }
+
+    impl Client {
+        fn request(&mut self, command: &str, arguments: Value) {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            write_message(&mut self.requests, &request).unwrap();
+        }
+
+        fn receive(&mut self) -> Value {
+            let body = read_message(&mut self.messages).unwrap().unwrap();
+            return serde_json::from_slice(&body).unwrap();
+        }
+
+        /// Skips to the response to `command`, checking it succeeded.
+        fn response(&mut self, command: &str) -> Value {
+            loop {
+                let message = self.receive();
+                if message["type"] == "response" && message["command"] == command {
+                    assert_eq!(message["success"], true, "{}", message);
+                    return message["body"].clone();
+                }
+            }
+        }
+
+        fn event(&mut self, event: &str) -> Value {
+            loop {
+                let message = self.receive();
+                if message["type"] == "event" && message["event"] == event {
+                    return message["body"].clone();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn debugs_a_program_from_launch_to_exit() {
+        let path = std::env::temp_dir().join(format!("dap-session-{}.lox", std::process::id()));
+        fs::write(&path, "var greeting = \"hi\";\nprint greeting;\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let (requests, input) = pipe();
+        let (output, messages) = pipe();
+        let adapter = thread::spawn(move || serve(input, output).unwrap());
+        let mut client = Client {
+            requests,
+            messages,
+            seq: 0,
+        };
+
+        client.request("initialize", json!({ "adapterID": "lox" }));
+        let capabilities = client.response("initialize");
+        assert_eq!(capabilities["supportsConfigurationDoneRequest"], true);
+        client.event("initialized");
+        client.request("launch", json!({ "program": path }));
+        client.response("launch");
+        let breakpoints = json!({ "source": { "path": path }, "breakpoints": [{ "line": 2 }] });
+        client.request("setBreakpoints", breakpoints);
+        assert_eq!(
+            client.response("setBreakpoints"),
+            json!({ "breakpoints": [{ "verified": true, "line": 2 }] })
+        );
+        client.request("configurationDone", json!({}));
+        client.response("configurationDone");
+
+        let stopped = client.event("stopped");
+        assert_eq!(stopped["reason"], "breakpoint");
+        client.request("variables", json!({ "variablesReference": GLOBALS }));
+        let variables = client.response("variables");
+        assert!(
+            variables["variables"].as_array().unwrap().contains(
+                &json!({ "name": "greeting", "value": "\"hi\"", "variablesReference": 0 })
+            ),
+            "{}",
+            variables
+        );
+
+        client.request("continue", json!({ "threadId": THREAD }));
+        client.response("continue");
+        assert_eq!(client.event("output")["output"], "hi\n");
+        assert_eq!(client.event("exited"), json!({ "exitCode": 0 }));
+        client.event("terminated");
+        client.request("disconnect", json!({}));
+        client.response("disconnect");
+
+        assert_eq!(adapter.join().unwrap(), 0);
+        fs::remove_file(&path).unwrap();
+    }
+}
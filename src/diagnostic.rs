@@ -0,0 +1,93 @@
+use crate::scanner::Span;
+use crate::LoxError;
+
+/// Renders every error followed by the source line it points at, with the
+/// offending span underlined by carets.
+pub fn render(source: &str, error: &LoxError) -> String {
+    let mut output = String::new();
+    match error {
+        LoxError::Compile(errors) => {
+            for error in errors {
+                let (line, column, span) = error.location();
+                output += &format!("{}\n", error);
+                output += &snippet(source, line, column, span);
+            }
+        }
+        LoxError::Runtime(error) => {
+            let (line, column, span) = error.location();
+            output += &format!("{}\n", error);
+            output += &snippet(source, line, column, span);
+        }
+    }
+    return output;
+}
+
+fn snippet(source: &str, line: usize, column: usize, span: Span) -> String {
+    let Some(text) = source.split('\n').nth(line - 1) else {
+        return String::new();
+    };
+    let line_start = text.as_ptr() as usize - source.as_ptr() as usize;
+    let line_end = line_start + text.len();
+
+    // Underline the part of the span on this line, or a single caret at the column.
+    let start = span.start.clamp(line_start, line_end);
+    let end = span.end.clamp(line_start, line_end);
+    let (padding, width) = if start < end {
+        (
+            source[line_start..start].chars().count(),
+            source[start..end].chars().count(),
+        )
+    } else {
+        (column - 1, 1)
+    };
+
+    let gutter = " ".repeat(line.to_string().len());
+    return format!(
+        "{gutter}--> {line}:{column}\n{gutter} |\n{line} | {text}\n{gutter} | {}{}\n",
+        " ".repeat(padding),
+        "^".repeat(width),
+        text = text.trim_end_matches('\r'),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interpreter;
+
+    #[test]
+    fn underlines_the_span_in_characters() {
+        let source = "var é = \"é\";\nprint é - 1;\n";
+        let error = Interpreter::new().run(source).unwrap_err();
+        assert_eq!(
+            render(source, &error),
+            "Operands must be numbers.
+[line 2]
+ --> 2:9
+  |
+2 | print é - 1;
+  |       ^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn renders_each_compile_error_with_its_own_snippet() {
+        let source = "return 1;\nprint this;\n";
+        let error = Interpreter::new().run(source).unwrap_err();
+        assert_eq!(
+            render(source, &error),
+            "[line 1] Error at 'return': Can't return from top-level code.
+ --> 1:1
+  |
+1 | return 1;
+  | ^^^^^^
+[line 2] Error at 'this': Can't use 'this' outside of a class.
+ --> 2:7
+  |
+2 | print this;
+  |       ^^^^
+"
+        );
+    }
+}
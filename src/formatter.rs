@@ -0,0 +1,512 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::ast::{Expression, FunctionDeclaration, Statement};
+use crate::parser::{ParseError, Parser};
+use crate::scanner::{Comment, Scanner, Token, TokenType};
+use crate::LoxError;
+
+const INDENT: &str = "  ";
+
+/// Reformats a whole program: two-space indentation, one statement per
+/// line, single spaces around binary operators and opening braces on the
+/// line that introduces them. Comments stay where they were and at most one
+/// blank line between statements is kept. A comment inside a statement ends
+/// its line, and the statement carries on two indents further in.
+pub fn format(source: &str) -> Result<String, LoxError> {
+    let (tokens, comments, errors) = Scanner::scan_with_comments(source.to_string());
+    if !errors.is_empty() {
+        return Err(LoxError::Compile(
+            errors.into_iter().map(ParseError::from).collect(),
+        ));
+    }
+    let statements = Parser::new(tokens.clone())
+        .parse_program()
+        .map_err(LoxError::Compile)?;
+
+    let mut formatter = Formatter::new(&tokens, comments);
+    for statement in &statements {
+        formatter.statement(statement);
+    }
+    formatter.leading_comments();
+    return Ok(formatter.output);
+}
+
+/// Prints the syntax tree while walking a cursor through the tokens it was
+/// parsed from. The tree doesn't keep braces, keywords or comments, and the
+/// parser desugars `for` loops; the cursor recovers all of that.
+struct Formatter<'a> {
+    tokens: &'a [Token],
+    /// Comments not printed yet, each with the index of the token after it.
+    comments: VecDeque<(usize, Comment)>,
+    cursor: usize,
+    output: String,
+    depth: usize,
+    /// The source line of whatever was printed last, to carry blank lines
+    /// over.
+    last_line: usize,
+    /// Set at the start of the file and of every block, where blank lines
+    /// are dropped.
+    at_start: bool,
+    /// Set when a comment inside a statement broke its line, so whatever
+    /// is printed next continues the statement on a new, further indented
+    /// line.
+    continued: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(tokens: &'a [Token], comments: Vec<Comment>) -> Self {
+        let comments = comments
+            .into_iter()
+            .map(|comment| {
+                let next = tokens.partition_point(|token| token.span.start < comment.span.end);
+                (next, comment)
+            })
+            .collect();
+        Formatter {
+            tokens,
+            comments,
+            cursor: 0,
+            output: String::new(),
+            depth: 0,
+            last_line: 0,
+            at_start: true,
+            continued: false,
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        self.leading_comments();
+        self.separate(self.peek().line);
+        self.indent();
+        self.statement_inline(statement);
+        self.end_line();
+    }
+
+    fn method(&mut self, declaration: &Rc<FunctionDeclaration>) {
+        self.leading_comments();
+        self.separate(self.peek().line);
+        self.indent();
+        self.function(declaration);
+        self.end_line();
+    }
+
+    /// Prints a statement from the cursor onwards, without indenting it or
+    /// ending the line.
+    fn statement_inline(&mut self, statement: &Statement) {
+        if matches!(self.peek()._type, TokenType::For) {
+            self.for_loop(statement);
+            return;
+        }
+        match statement {
+            Statement::Expression(expression) => {
+                self.expression(expression);
+                self.token();
+            }
+            Statement::Print(expression) => {
+                self.keyword();
+                self.expression(expression);
+                self.token();
+            }
+            Statement::Var(_, initializer) => {
+                self.keyword();
+                self.token();
+                if let Some(initializer) = initializer {
+                    self.space();
+                    self.keyword();
+                    self.expression(initializer);
+                }
+                self.token();
+            }
+            Statement::Block(statements) => self.braced(statements, Formatter::statement),
+            Statement::If(condition, then_branch, else_branch) => {
+                self.keyword();
+                self.condition(condition);
+                self.body(then_branch);
+                if let Some(else_branch) = else_branch {
+                    let after_brace =
+                        matches!(self.tokens[self.cursor - 1]._type, TokenType::RightBrace);
+                    if after_brace && !self.continued {
+                        self.space();
+                    } else {
+                        self.end_line();
+                        self.indent();
+                    }
+                    self.keyword();
+                    self.statement_inline(else_branch);
+                }
+            }
+            Statement::While(condition, body) => {
+                self.keyword();
+                self.condition(condition);
+                self.body(body);
+            }
+            Statement::Function(declaration) => {
+                self.keyword();
+                self.function(declaration);
+            }
+            Statement::Return(_, value) => {
+                self.token();
+                if let Some(value) = value {
+                    self.space();
+                    self.expression(value);
+                }
+                self.token();
+            }
+            Statement::Class(_, superclass, methods) => {
+                self.keyword();
+                self.token();
+                if superclass.is_some() {
+                    self.space();
+                    self.keyword();
+                    self.token();
+                }
+                self.space();
+                self.braced(methods, Formatter::method);
+            }
+        }
+    }
+
+    /// Prints a `for` loop, undoing the parser's desugaring: an initializer
+    /// wraps the loop in a block, and an increment is appended to the body.
+    fn for_loop(&mut self, statement: &Statement) {
+        self.keyword();
+        self.token();
+
+        let mut statement = statement;
+        if matches!(self.peek()._type, TokenType::Semicolon) {
+            self.token();
+        } else {
+            let Statement::Block(statements) = statement else {
+                unreachable!("a loop with an initializer is wrapped in a block");
+            };
+            self.statement_inline(&statements[0]);
+            statement = &statements[1];
+        }
+
+        let Statement::While(condition, body) = statement else {
+            unreachable!("a for loop is desugared into a while loop");
+        };
+        if !matches!(self.peek()._type, TokenType::Semicolon) {
+            self.space();
+            self.expression(condition);
+        }
+        self.token();
+
+        let mut body: &Statement = body;
+        if !matches!(self.peek()._type, TokenType::RightParen) {
+            let Statement::Block(statements) = body else {
+                unreachable!("a loop with an increment has it appended to its body");
+            };
+            let Statement::Expression(increment) = &statements[1] else {
+                unreachable!("the increment is an expression statement");
+            };
+            self.space();
+            self.expression(increment);
+            body = &statements[0];
+        }
+        self.token();
+        self.body(body);
+    }
+
+    fn function(&mut self, declaration: &FunctionDeclaration) {
+        self.token();
+        self.token();
+        for i in 0..declaration.params.len() {
+            if i > 0 {
+                self.token();
+                self.space();
+            }
+            self.token();
+        }
+        self.token();
+        self.space();
+        self.braced(&declaration.body, Formatter::statement);
+    }
+
+    /// Prints `items` between braces, one per line and indented, or `{}` if
+    /// there's nothing inside.
+    fn braced<T>(&mut self, items: &[T], item: fn(&mut Formatter<'a>, &T)) {
+        let comment_inside = self
+            .comments
+            .front()
+            .is_some_and(|(next, _)| *next <= self.cursor + 1);
+        self.token();
+        if items.is_empty() && !comment_inside {
+            self.token();
+            return;
+        }
+
+        self.end_line();
+        self.depth += 1;
+        self.at_start = true;
+        for each in items {
+            item(self, each);
+        }
+        self.leading_comments();
+        self.depth -= 1;
+        self.indent();
+        self.token();
+    }
+
+    /// Prints the body of an `if` or loop on the same line as its header.
+    fn body(&mut self, body: &Statement) {
+        self.space();
+        self.statement_inline(body);
+    }
+
+    fn condition(&mut self, condition: &Expression) {
+        self.token();
+        self.expression(condition);
+        self.token();
+    }
+
+    /// Prints an expression token by token, so comments inside it stay
+    /// where they were.
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Literal(_) | Expression::Variable(..) | Expression::This(..) => {
+                self.token();
+            }
+            Expression::Unary(_, right) => {
+                self.token();
+                self.expression(right);
+            }
+            Expression::Binary(left, _, right) | Expression::Logical(left, _, right) => {
+                self.expression(left);
+                self.space();
+                self.keyword();
+                self.expression(right);
+            }
+            Expression::Grouping(inner) => {
+                self.token();
+                self.expression(inner);
+                self.token();
+            }
+            Expression::Assign(_, _, value) => {
+                self.keyword();
+                self.keyword();
+                self.expression(value);
+            }
+            Expression::Call(callee, _, arguments) => {
+                self.expression(callee);
+                self.token();
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        self.keyword();
+                    }
+                    self.expression(argument);
+                }
+                self.token();
+            }
+            Expression::Get(object, _) => {
+                self.receiver(object);
+                self.token();
+                self.token();
+            }
+            Expression::Set(object, _, value) => {
+                self.receiver(object);
+                self.token();
+                self.keyword();
+                self.keyword();
+                self.expression(value);
+            }
+            Expression::Super(..) => {
+                self.token();
+                self.token();
+                self.token();
+            }
+        }
+    }
+
+    /// Prints the object of a property access. A number needs a space before
+    /// the dot, or `1 .x` would scan back as the number `1.` followed by `x`.
+    fn receiver(&mut self, object: &Expression) {
+        self.expression(object);
+        if let Expression::Literal(token) = object {
+            if matches!(token._type, TokenType::Number(_)) {
+                self.space();
+            }
+        }
+    }
+
+    /// Prints the comments before the token at the cursor, each on its own
+    /// line.
+    fn leading_comments(&mut self) {
+        while let Some(&(next, _)) = self.comments.front() {
+            if next > self.cursor {
+                return;
+            }
+            let (_, comment) = self.comments.pop_front().unwrap();
+            self.separate(comment.line);
+            self.indent();
+            self.output += &comment.text;
+            self.output.push('\n');
+            self.last_line = comment.line;
+        }
+    }
+
+    /// Ends the line after the last token printed, unless a comment after
+    /// it already did.
+    fn end_line(&mut self) {
+        if self.continued {
+            self.continued = false;
+            return;
+        }
+        self.output.push('\n');
+        self.last_line = self.tokens[self.cursor - 1].line;
+    }
+
+    /// Prints the comments inside a statement that come before the token at
+    /// the cursor, each on a line of its own.
+    fn inner_comments(&mut self) {
+        while let Some(&(next, _)) = self.comments.front() {
+            if next > self.cursor {
+                return;
+            }
+            let (_, comment) = self.comments.pop_front().unwrap();
+            if !self.continued {
+                self.output
+                    .truncate(self.output.trim_end_matches(' ').len());
+                self.output.push('\n');
+            }
+            self.output += &INDENT.repeat(self.depth + 2);
+            self.output += &comment.text;
+            self.output.push('\n');
+            self.last_line = comment.line;
+            self.continued = true;
+        }
+    }
+
+    /// Prints a comment that follows the last token printed on its line,
+    /// breaking the line after it.
+    fn trailing_comment(&mut self) {
+        let last = &self.tokens[self.cursor - 1];
+        let Some((next, comment)) = self.comments.front() else {
+            return;
+        };
+        if *next != self.cursor || comment.line != last.line {
+            return;
+        }
+        self.output.push(' ');
+        self.output += &comment.text;
+        self.output.push('\n');
+        self.last_line = comment.line;
+        self.continued = true;
+        self.comments.pop_front();
+    }
+
+    /// Keeps a single blank line before something starting on `line` if the
+    /// source had at least one there.
+    fn separate(&mut self, line: usize) {
+        if !self.at_start && line > self.last_line + 1 {
+            self.output.push('\n');
+        }
+        self.at_start = false;
+    }
+
+    fn indent(&mut self) {
+        self.output += &INDENT.repeat(self.depth);
+    }
+
+    fn peek(&self) -> &'a Token {
+        return &self.tokens[self.cursor];
+    }
+
+    /// Prints the token at the cursor as written, along with any comments
+    /// around it.
+    fn token(&mut self) {
+        self.inner_comments();
+        if self.continued {
+            self.output += &INDENT.repeat(self.depth + 2);
+            self.continued = false;
+        }
+        self.output += self.peek().lexeme();
+        self.cursor += 1;
+        self.trailing_comment();
+    }
+
+    /// Prints the token at the cursor followed by a space.
+    fn keyword(&mut self) {
+        self.token();
+        self.space();
+    }
+
+    /// Separates two tokens on a line, which a comment may have ended.
+    fn space(&mut self) {
+        if !self.continued {
+            self.output.push(' ');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+
+    #[test]
+    fn keeps_comments_inside_statements_on_their_line() {
+        let source = "\
+fun add(a,   // first
+   b) {
+  return a+b;
+}
+while (x < 3 // bound
+) {
+  x = x + 1;
+}
+if (a // flag
+  and b) print 1;
+print 1 // before semi
+;
+var y = 1 +
+  // own line
+  2;
+";
+        let expected = "\
+fun add(a, // first
+    b) {
+  return a + b;
+}
+while (x < 3 // bound
+    ) {
+  x = x + 1;
+}
+if (a // flag
+    and b) print 1;
+print 1 // before semi
+    ;
+var y = 1 +
+    // own line
+    2;
+";
+        let formatted = format(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn keeps_comments_around_braces() {
+        let source = "\
+fun f() { // trailing
+}
+fun g() {
+  // only a comment
+}
+if (true) {
+  print 1;
+} // after brace
+else {
+  print 2;
+}
+";
+        assert_eq!(format(source).unwrap(), source);
+    }
+
+    #[test]
+    fn normalises_crlf_line_endings() {
+        let source = "// first\r\nprint 1;\r\nif (true) {\r\n  print 2; // second\r\n}\r\n";
+        let expected = "// first\nprint 1;\nif (true) {\n  print 2; // second\n}\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+}
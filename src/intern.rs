@@ -0,0 +1,228 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+
+/// An interned string. Equal texts share one allocation, so comparing two
+/// symbols is a pointer comparison, and the hash is computed once when the
+/// text is first interned.
+#[derive(Clone)]
+pub struct Symbol(Rc<Interned>);
+
+struct Interned {
+    hash: u32,
+    text: Box<str>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+impl Symbol {
+    /// Returns the symbol for `text`, interning it if no live symbol has the
+    /// same text.
+    pub fn new(text: &str) -> Self {
+        return INTERNER.with(|interner| interner.borrow_mut().intern(text));
+    }
+
+    /// The FNV-1a hash of the text, computed at interning time.
+    pub fn hash_code(&self) -> u32 {
+        return self.0.hash;
+    }
+
+    pub fn as_str(&self) -> &str {
+        return &self.0.text;
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        return &self.0.text;
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        return Rc::ptr_eq(&self.0, &other.0);
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        return self.as_str() == other;
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        return self.as_str() == *other;
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u32(self.0.hash);
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(text: &str) -> Self {
+        return Symbol::new(text);
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(text: String) -> Self {
+        return Symbol::new(&text);
+    }
+}
+
+fn hash_string(text: &str) -> u32 {
+    let mut hash: u32 = 2166136261;
+    for byte in text.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(16777619);
+    }
+    return hash;
+}
+
+/// The set of live symbols, open-addressed by hash. Slots hold weak
+/// references, so a symbol is freed once nothing else uses it; its dead slot
+/// is reused by a later insertion or dropped when the set is rebuilt.
+struct Interner {
+    slots: Vec<Option<Weak<Interned>>>,
+    /// Occupied slots, dead or alive.
+    count: usize,
+}
+
+impl Interner {
+    fn new() -> Self {
+        Interner {
+            slots: Vec::new(),
+            count: 0,
+        }
+    }
+
+    fn intern(&mut self, text: &str) -> Symbol {
+        if (self.count + 1) * 4 > self.slots.len() * 3 {
+            self.rebuild();
+        }
+
+        let hash = hash_string(text);
+        let mask = self.slots.len() - 1;
+        let mut index = hash as usize & mask;
+        let mut dead = None;
+        while let Some(slot) = &self.slots[index] {
+            match slot.upgrade() {
+                Some(interned) if interned.hash == hash && &*interned.text == text => {
+                    return Symbol(interned);
+                }
+                Some(_) => {}
+                None => {
+                    dead.get_or_insert(index);
+                }
+            }
+            index = (index + 1) & mask;
+        }
+
+        let interned = Rc::new(Interned {
+            hash,
+            text: text.into(),
+        });
+        if dead.is_none() {
+            self.count += 1;
+        }
+        self.slots[dead.unwrap_or(index)] = Some(Rc::downgrade(&interned));
+        return Symbol(interned);
+    }
+
+    /// Drops dead slots and grows the table if the live symbols alone would
+    /// fill more than half of it.
+    fn rebuild(&mut self) {
+        let live: Vec<Rc<Interned>> = self
+            .slots
+            .iter()
+            .flatten()
+            .filter_map(Weak::upgrade)
+            .collect();
+
+        let capacity = (live.len() * 2).next_power_of_two().max(8);
+        self.slots = vec![None; capacity];
+        self.count = live.len();
+        let mask = capacity - 1;
+        for interned in live {
+            let mut index = interned.hash as usize & mask;
+            while self.slots[index].is_some() {
+                index = (index + 1) & mask;
+            }
+            self.slots[index] = Some(Rc::downgrade(&interned));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How many symbols with `text` the interner still holds.
+    fn interned(text: &str) -> usize {
+        return INTERNER.with(|interner| {
+            interner
+                .borrow()
+                .slots
+                .iter()
+                .flatten()
+                .filter_map(Weak::upgrade)
+                .filter(|interned| &*interned.text == text)
+                .count()
+        });
+    }
+
+    #[test]
+    fn equal_texts_share_a_symbol() {
+        let first = Symbol::new("shared");
+        let second = Symbol::from(String::from("shared"));
+        assert_eq!(first, second);
+        assert_ne!(first, Symbol::new("other"));
+        assert_eq!(interned("shared"), 1);
+    }
+
+    #[test]
+    fn symbols_are_freed_with_their_last_use() {
+        let symbol = Symbol::new("temporary");
+        let copy = symbol.clone();
+        drop(symbol);
+        assert_eq!(interned("temporary"), 1);
+        assert_eq!(Symbol::new("temporary"), copy);
+
+        drop(copy);
+        assert_eq!(interned("temporary"), 0);
+    }
+
+    #[test]
+    fn dead_slots_are_reclaimed() {
+        let kept = Symbol::new("kept");
+        for n in 0..1000 {
+            Symbol::new(&format!("temporary{}", n));
+        }
+        INTERNER.with(|interner| assert!(interner.borrow().slots.len() <= 16));
+        assert_eq!(Symbol::new("kept"), kept);
+    }
+}
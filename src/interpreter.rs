@@ -0,0 +1,664 @@
+mod debug;
+mod environment;
+mod object;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use thiserror::Error;
+
+use self::debug::Frame;
+pub use self::debug::{DebugHook, StackFrame};
+use self::environment::Environment;
+pub(crate) use self::object::clock;
+pub use self::object::{
+    LoxCallable, LoxClass, LoxFunction, LoxInstance, NativeFn, NativeFunction, Object,
+};
+use crate::ast::{Expression, Statement};
+use crate::intern::Symbol;
+use crate::optimizer;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::{Span, Token, TokenType};
+use crate::table::Table;
+use crate::LoxError;
+
+/// How deep calls may nest before a program fails with a stack overflow,
+/// counting the top level as a call. The interpreter recurses on the native
+/// stack for every call, roughly 25 KB of it per call in a debug build, so
+/// the limit is low enough to fit in the usual 8 MB main-thread stack. The VM
+/// uses the same limit.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    locals: HashMap<usize, usize>,
+    environment: Rc<RefCell<Environment>>,
+    optimize: bool,
+    /// How many Lox functions are running, so deep recursion is reported as
+    /// a stack overflow rather than overflowing the native stack.
+    call_depth: usize,
+    max_call_depth: usize,
+    debug_hook: Option<Box<dyn DebugHook>>,
+    /// The calls in progress, tracked only while a debug hook is installed.
+    frames: Vec<Frame>,
+}
+
+#[derive(Debug, Error)]
+pub enum RuntimeError {
+    #[error("Operand must be a number.\n[line {}]", .0.line)]
+    OperandMustBeNumber(Token, Span),
+    #[error("Operands must be numbers.\n[line {}]", .0.line)]
+    OperandsMustBeNumbers(Token, Span),
+    #[error("Operands must be two numbers or two strings.\n[line {}]", .0.line)]
+    OperandsMustBeNumbersOrStrings(Token, Span),
+    #[error("Undefined variable '{}'.\n[line {}]", .0.lexeme, .0.line)]
+    UndefinedVariable(Token),
+    #[error("Undefined property '{}'.\n[line {}]", .0.lexeme, .0.line)]
+    UndefinedProperty(Token),
+    #[error("Can only call functions and classes.\n[line {}]", .0.line)]
+    NotCallable(Token, Span),
+    #[error("Expected {expected} arguments but got {got}.\n[line {}]", .paren.line)]
+    ArityMismatch {
+        paren: Token,
+        span: Span,
+        expected: usize,
+        got: usize,
+    },
+    #[error("Only instances have properties.\n[line {}]", .0.line)]
+    OnlyInstancesHaveProperties(Token),
+    #[error("Only instances have fields.\n[line {}]", .0.line)]
+    OnlyInstancesHaveFields(Token),
+    #[error("Superclass must be a class.\n[line {}]", .0.line)]
+    SuperclassMustBeClass(Token),
+    #[error("Stack overflow.\n[line {}]", .0.line)]
+    StackOverflow(Token, Span),
+    #[error("{message}\n[line {}]", .paren.line)]
+    Native { paren: Token, message: String },
+    /// A [`DebugHook`] stopped the program before the statement on `line`.
+    #[error("Stopped by the debugger.\n[line {line}]")]
+    Stopped { line: usize },
+}
+
+impl RuntimeError {
+    /// Line and column of the offending token plus the span to underline,
+    /// which widens to the whole expression for operand and call errors.
+    pub fn location(&self) -> (usize, usize, Span) {
+        match self {
+            RuntimeError::OperandMustBeNumber(token, span)
+            | RuntimeError::OperandsMustBeNumbers(token, span)
+            | RuntimeError::OperandsMustBeNumbersOrStrings(token, span)
+            | RuntimeError::NotCallable(token, span)
+            | RuntimeError::StackOverflow(token, span)
+            | RuntimeError::ArityMismatch {
+                paren: token, span, ..
+            } => (token.line, token.column, *span),
+            RuntimeError::UndefinedVariable(token)
+            | RuntimeError::UndefinedProperty(token)
+            | RuntimeError::OnlyInstancesHaveProperties(token)
+            | RuntimeError::OnlyInstancesHaveFields(token)
+            | RuntimeError::SuperclassMustBeClass(token)
+            | RuntimeError::Native { paren: token, .. } => (token.line, token.column, token.span),
+            RuntimeError::Stopped { line } => (*line, 1, Span::new(0, 0)),
+        }
+    }
+
+    /// What went wrong, without the line the full message ends with.
+    pub fn message(&self) -> String {
+        match self {
+            RuntimeError::OperandMustBeNumber(..) => "Operand must be a number.".to_string(),
+            RuntimeError::OperandsMustBeNumbers(..) => "Operands must be numbers.".to_string(),
+            RuntimeError::OperandsMustBeNumbersOrStrings(..) => {
+                "Operands must be two numbers or two strings.".to_string()
+            }
+            RuntimeError::UndefinedVariable(name) => {
+                format!("Undefined variable '{}'.", name.lexeme)
+            }
+            RuntimeError::UndefinedProperty(name) => {
+                format!("Undefined property '{}'.", name.lexeme)
+            }
+            RuntimeError::NotCallable(..) => "Can only call functions and classes.".to_string(),
+            RuntimeError::ArityMismatch { expected, got, .. } => {
+                format!("Expected {} arguments but got {}.", expected, got)
+            }
+            RuntimeError::OnlyInstancesHaveProperties(_) => {
+                "Only instances have properties.".to_string()
+            }
+            RuntimeError::OnlyInstancesHaveFields(_) => "Only instances have fields.".to_string(),
+            RuntimeError::SuperclassMustBeClass(_) => "Superclass must be a class.".to_string(),
+            RuntimeError::StackOverflow(..) => "Stack overflow.".to_string(),
+            RuntimeError::Native { message, .. } => message.clone(),
+            RuntimeError::Stopped { .. } => "Stopped by the debugger.".to_string(),
+        }
+    }
+}
+
+pub type InterpreterResult = Result<Object, RuntimeError>;
+
+/// Non-local exits out of statement execution: either a `return` unwinding to
+/// the enclosing call or a runtime error unwinding to the top.
+enum Unwind {
+    Return(Object),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+type ExecuteResult = Result<(), Unwind>;
+
+impl Interpreter {
+    pub fn new() -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        let mut interpreter = Interpreter {
+            environment: Rc::clone(&globals),
+            globals,
+            locals: HashMap::new(),
+            optimize: true,
+            call_depth: 0,
+            max_call_depth: MAX_CALL_DEPTH,
+            debug_hook: None,
+            frames: Vec::new(),
+        };
+        interpreter.define_native("clock", 0, clock);
+        return interpreter;
+    }
+
+    /// Exposes a host function to scripts as a global called `name`. The
+    /// interpreter checks `arity` before calling, so `function` always sees
+    /// exactly that many arguments. [`Vm::define_native`](crate::Vm::define_native)
+    /// does the same for the bytecode backend.
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&[Object]) -> Result<Object, String> + 'static,
+    {
+        let native = NativeFunction {
+            arity,
+            function: Box::new(function),
+        };
+        self.globals
+            .borrow_mut()
+            .define(Symbol::new(name), Object::NativeFunction(Rc::new(native)));
+    }
+
+    /// Whether [`Interpreter::run`] and [`Interpreter::run_line`] fold
+    /// constants and drop dead branches before executing. On by default.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Sets how deep calls may nest before a program fails with a stack
+    /// overflow, in place of [`MAX_CALL_DEPTH`]. Lower it to run scripts on
+    /// a thread with a small stack.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Binds `value` to a global variable, replacing any previous binding.
+    pub fn define_global(&mut self, name: &str, value: impl Into<Object>) {
+        self.globals
+            .borrow_mut()
+            .define(Symbol::new(name), value.into());
+    }
+
+    pub fn interpret(&mut self, statements: &[Statement]) -> Result<(), RuntimeError> {
+        for statement in statements {
+            match self.execute(statement) {
+                Ok(()) => {}
+                Err(Unwind::Return(_)) => return Ok(()),
+                Err(Unwind::Error(error)) => return Err(error),
+            }
+        }
+        return Ok(());
+    }
+
+    fn execute(&mut self, statement: &Statement) -> ExecuteResult {
+        if self.debug_hook.is_some() {
+            self.debug_statement(statement)?;
+        }
+        match statement {
+            Statement::Expression(expression) => {
+                self.evaluate(expression)?;
+            }
+            Statement::Print(expression) => {
+                let value = self.evaluate(expression)?;
+                self.print(&value.to_string());
+            }
+            Statement::Var(name, initializer) => {
+                let value = match initializer {
+                    Some(expression) => self.evaluate(expression)?,
+                    None => Object::Nil,
+                };
+                self.environment
+                    .borrow_mut()
+                    .define(name.lexeme.clone(), value);
+            }
+            Statement::Block(statements) => {
+                let environment = Environment::new_enclosed(Rc::clone(&self.environment));
+                self.execute_block(statements, Rc::new(RefCell::new(environment)))?;
+            }
+            Statement::If(condition, then_branch, else_branch) => {
+                let condition = self.evaluate(condition)?;
+                if Interpreter::is_truthy(&condition) {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Statement::While(condition, body) => {
+                while Interpreter::is_truthy(&self.evaluate(condition)?) {
+                    self.execute(body)?;
+                }
+            }
+            Statement::Function(declaration) => {
+                let function = LoxFunction {
+                    declaration: Rc::clone(declaration),
+                    closure: Rc::clone(&self.environment),
+                    is_initializer: false,
+                };
+                self.environment.borrow_mut().define(
+                    declaration.name.lexeme.clone(),
+                    Object::Function(Rc::new(function)),
+                );
+            }
+            Statement::Return(_, value) => {
+                let value = match value {
+                    Some(expression) => self.evaluate(expression)?,
+                    None => Object::Nil,
+                };
+                return Err(Unwind::Return(value));
+            }
+            Statement::Class(name, superclass, declarations) => {
+                let superclass = match superclass {
+                    Some(expression) => match self.evaluate(expression)? {
+                        Object::Class(class) => Some(class),
+                        _ => {
+                            let Expression::Variable(_, superclass_name) = expression else {
+                                unreachable!("superclass is always parsed as a variable");
+                            };
+                            return Err(Unwind::Error(RuntimeError::SuperclassMustBeClass(
+                                superclass_name.clone(),
+                            )));
+                        }
+                    },
+                    None => None,
+                };
+
+                self.environment
+                    .borrow_mut()
+                    .define(name.lexeme.clone(), Object::Nil);
+
+                let enclosing = Rc::clone(&self.environment);
+                if let Some(superclass) = &superclass {
+                    let mut environment = Environment::new_enclosed(Rc::clone(&enclosing));
+                    environment.define(Symbol::new("super"), Object::Class(Rc::clone(superclass)));
+                    self.environment = Rc::new(RefCell::new(environment));
+                }
+
+                let mut methods = Table::new();
+                for declaration in declarations {
+                    let function = LoxFunction {
+                        declaration: Rc::clone(declaration),
+                        closure: Rc::clone(&self.environment),
+                        is_initializer: declaration.name.lexeme == "init",
+                    };
+                    methods.insert(declaration.name.lexeme.clone(), Rc::new(function));
+                }
+
+                let class = LoxClass {
+                    name: name.lexeme.clone(),
+                    superclass,
+                    methods,
+                };
+                self.environment = enclosing;
+                self.environment
+                    .borrow_mut()
+                    .assign(name, Object::Class(Rc::new(class)))?;
+            }
+        }
+        return Ok(());
+    }
+
+    fn execute_block(
+        &mut self,
+        statements: &[Statement],
+        environment: Rc<RefCell<Environment>>,
+    ) -> ExecuteResult {
+        let previous = mem::replace(&mut self.environment, environment);
+        let mut result = Ok(());
+        for statement in statements {
+            result = self.execute(statement);
+            if result.is_err() {
+                break;
+            }
+        }
+        // The enclosing scope comes back even when a statement fails.
+        self.environment = previous;
+        return result;
+    }
+
+    pub fn evaluate(&mut self, expression: &Expression) -> InterpreterResult {
+        match expression {
+            Expression::Literal(value) => match &value._type {
+                TokenType::Nil => return Ok(Object::Nil),
+                TokenType::True => return Ok(Object::Boolean(true)),
+                TokenType::False => return Ok(Object::Boolean(false)),
+                TokenType::Number(n) => return Ok(Object::Number(*n)),
+                TokenType::StringLiteral(s) => return Ok(Object::String(s.clone())),
+                _ => unreachable!("the parser only builds literals from literal tokens"),
+            },
+            Expression::Unary(operator, value) => {
+                let right = self.evaluate(value)?;
+                match operator._type {
+                    TokenType::Minus => match right {
+                        Object::Number(n) => return Ok(Object::Number(-n)),
+                        _ => {
+                            return Err(RuntimeError::OperandMustBeNumber(
+                                operator.clone(),
+                                expression.span(),
+                            ))
+                        }
+                    },
+                    TokenType::Bang => return Ok(Object::Boolean(!Interpreter::is_truthy(&right))),
+                    _ => unreachable!("the parser only builds unary '-' and '!'"),
+                }
+            }
+            Expression::Binary(lhs, operator, rhs) => {
+                let lhs = self.evaluate(lhs)?;
+                let rhs = self.evaluate(rhs)?;
+
+                match operator._type {
+                    TokenType::BangEqual => {
+                        return Ok(Object::Boolean(!Interpreter::is_equal(&lhs, &rhs)))
+                    }
+                    TokenType::EqualEqual => {
+                        return Ok(Object::Boolean(Interpreter::is_equal(&lhs, &rhs)))
+                    }
+                    TokenType::Plus => match (lhs, rhs) {
+                        (Object::Number(n), Object::Number(m)) => return Ok(Object::Number(n + m)),
+                        (Object::String(s), Object::String(t)) => {
+                            return Ok(Object::String(Symbol::new(&format!("{}{}", s, t))))
+                        }
+                        _ => {
+                            return Err(RuntimeError::OperandsMustBeNumbersOrStrings(
+                                operator.clone(),
+                                expression.span(),
+                            ))
+                        }
+                    },
+                    _ => {}
+                }
+
+                let (Object::Number(n), Object::Number(m)) = (lhs, rhs) else {
+                    return Err(RuntimeError::OperandsMustBeNumbers(
+                        operator.clone(),
+                        expression.span(),
+                    ));
+                };
+                match operator._type {
+                    TokenType::Minus => return Ok(Object::Number(n - m)),
+                    TokenType::Slash => return Ok(Object::Number(n / m)),
+                    TokenType::Star => return Ok(Object::Number(n * m)),
+                    TokenType::Greater => return Ok(Object::Boolean(n > m)),
+                    TokenType::GreaterEqual => return Ok(Object::Boolean(n >= m)),
+                    TokenType::Less => return Ok(Object::Boolean(n < m)),
+                    TokenType::LessEqual => return Ok(Object::Boolean(n <= m)),
+                    _ => unreachable!("the parser only builds binary arithmetic and comparisons"),
+                }
+            }
+            Expression::Grouping(value) => return self.evaluate(value),
+            Expression::Variable(id, name) => return self.look_up_variable(*id, name),
+            Expression::Assign(id, name, value) => {
+                let value = self.evaluate(value)?;
+                match self.locals.get(id) {
+                    Some(distance) => {
+                        Environment::assign_at(&self.environment, *distance, name, value.clone())
+                    }
+                    None => self.globals.borrow_mut().assign(name, value.clone())?,
+                }
+                return Ok(value);
+            }
+            Expression::Logical(lhs, operator, rhs) => {
+                let lhs = self.evaluate(lhs)?;
+
+                if matches!(operator._type, TokenType::Or) {
+                    if Interpreter::is_truthy(&lhs) {
+                        return Ok(lhs);
+                    }
+                } else if !Interpreter::is_truthy(&lhs) {
+                    return Ok(lhs);
+                }
+
+                return self.evaluate(rhs);
+            }
+            Expression::Call(callee, paren, arguments) => {
+                let callee = self.evaluate(callee)?;
+
+                let mut values = Vec::new();
+                for argument in arguments {
+                    values.push(self.evaluate(argument)?);
+                }
+
+                // Only calls that run a function body take a frame, as in
+                // the VM.
+                let takes_frame = match &callee {
+                    Object::Function(_) => true,
+                    Object::Class(class) => class.find_method(&Symbol::new("init")).is_some(),
+                    _ => false,
+                };
+                let function: Rc<dyn LoxCallable> = match callee {
+                    Object::Function(function) => function,
+                    Object::NativeFunction(function) => function,
+                    Object::Class(class) => class,
+                    _ => return Err(RuntimeError::NotCallable(paren.clone(), expression.span())),
+                };
+
+                if values.len() != function.arity() {
+                    return Err(RuntimeError::ArityMismatch {
+                        paren: paren.clone(),
+                        span: expression.span(),
+                        expected: function.arity(),
+                        got: values.len(),
+                    });
+                }
+
+                if takes_frame && self.call_depth + 1 >= self.max_call_depth {
+                    return Err(RuntimeError::StackOverflow(
+                        paren.clone(),
+                        expression.span(),
+                    ));
+                }
+
+                return function.call(self, paren, values);
+            }
+            Expression::Get(object, name) => match self.evaluate(object)? {
+                Object::Instance(instance) => return LoxInstance::get(&instance, name),
+                _ => return Err(RuntimeError::OnlyInstancesHaveProperties(name.clone())),
+            },
+            Expression::Set(object, name, value) => {
+                let Object::Instance(instance) = self.evaluate(object)? else {
+                    return Err(RuntimeError::OnlyInstancesHaveFields(name.clone()));
+                };
+                let value = self.evaluate(value)?;
+                instance.borrow_mut().set(name, value.clone());
+                return Ok(value);
+            }
+            Expression::This(id, keyword) => return self.look_up_variable(*id, keyword),
+            Expression::Super(id, keyword, method) => {
+                let distance = self.locals[id];
+                let superclass = Environment::get_at(&self.environment, distance, &keyword.lexeme);
+                let this =
+                    Environment::get_at(&self.environment, distance - 1, &Symbol::new("this"));
+                let (Some(Object::Class(superclass)), Some(Object::Instance(instance))) =
+                    (superclass, this)
+                else {
+                    unreachable!("the resolver binds 'super' and 'this' inside subclasses");
+                };
+
+                match superclass.find_method(&method.lexeme) {
+                    Some(function) => {
+                        return Ok(Object::Function(Rc::new(function.bind(instance))))
+                    }
+                    None => return Err(RuntimeError::UndefinedProperty(method.clone())),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn resolve(&mut self, id: usize, depth: usize) {
+        self.locals.insert(id, depth);
+    }
+
+    fn look_up_variable(&self, id: usize, name: &Token) -> InterpreterResult {
+        match self.locals.get(&id) {
+            Some(distance) => match Environment::get_at(&self.environment, *distance, &name.lexeme)
+            {
+                Some(value) => return Ok(value),
+                None => return Err(RuntimeError::UndefinedVariable(name.clone())),
+            },
+            None => return self.globals.borrow().get(name),
+        }
+    }
+
+    fn is_truthy(object: &Object) -> bool {
+        match object {
+            Object::Nil => false,
+            Object::Boolean(b) => *b,
+            _ => true,
+        }
+    }
+
+    fn is_equal(lhs: &Object, rhs: &Object) -> bool {
+        match (lhs, rhs) {
+            (Object::Nil, Object::Nil) => return true,
+            (Object::Nil, _) => return false,
+            (a, b) => return a == b,
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    /// Scans, parses, resolves and executes a whole program.
+    pub fn run(&mut self, source: &str) -> Result<(), LoxError> {
+        let mut statements = crate::parse_program(source)?;
+        Resolver::new(self)
+            .resolve(&statements)
+            .map_err(LoxError::Compile)?;
+        if self.optimize {
+            statements = optimizer::optimize(statements);
+        }
+        self.interpret(&statements)?;
+        return Ok(());
+    }
+
+    /// Runs one REPL entry against this interpreter's state. A bare expression
+    /// with no trailing semicolon evaluates to `Some(value)`.
+    pub fn run_line(&mut self, source: &str) -> Result<Option<Object>, LoxError> {
+        let tokens = crate::scan(source)?;
+
+        let mut parser = Parser::new(tokens.clone());
+        match parser.parse_bare_expression() {
+            Ok(mut expression) => {
+                Resolver::new(self)
+                    .resolve_bare_expression(&expression)
+                    .map_err(LoxError::Compile)?;
+                if self.optimize {
+                    expression = optimizer::optimize_expression(expression);
+                }
+                return Ok(Some(self.evaluate(&expression)?));
+            }
+            // No program ends where an expression does, so the entry was
+            // meant as an expression and its error is the one to report.
+            Err(error) if parser.is_at_end() => return Err(LoxError::Compile(vec![error])),
+            Err(_) => {}
+        }
+
+        let mut statements = Parser::new(tokens)
+            .parse_program()
+            .map_err(LoxError::Compile)?;
+        Resolver::new(self)
+            .resolve(&statements)
+            .map_err(LoxError::Compile)?;
+        if self.optimize {
+            statements = optimizer::optimize(statements);
+        }
+        self.interpret(&statements)?;
+        return Ok(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const RECURSE: &str = "fun f(n) { if (n > 0) f(n - 1); }\n";
+
+    fn is_stack_overflow(result: Result<(), LoxError>) -> bool {
+        return matches!(
+            result,
+            Err(LoxError::Runtime(RuntimeError::StackOverflow(..)))
+        );
+    }
+
+    #[test]
+    fn deep_recursion_fits_in_a_main_thread_stack() {
+        let run = thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(|| {
+                let mut interpreter = Interpreter::new();
+                let limit = format!("{}f({});", RECURSE, MAX_CALL_DEPTH - 2);
+                assert!(interpreter.run(&limit).is_ok());
+                let past_limit = format!("{}f({});", RECURSE, MAX_CALL_DEPTH - 1);
+                assert!(is_stack_overflow(interpreter.run(&past_limit)));
+            })
+            .unwrap();
+        run.join().unwrap();
+    }
+
+    #[test]
+    fn bare_expressions_on_a_line_evaluate_to_their_value() {
+        let mut interpreter = Interpreter::new();
+        let mut run_line = |line| {
+            let value = interpreter.run_line(line).unwrap();
+            return value.map(|value| value.to_string());
+        };
+        assert_eq!(run_line("1 + 2"), Some("3".to_string()));
+        assert_eq!(run_line("var a = 1;"), None);
+        assert_eq!(run_line("print a;"), None);
+        assert_eq!(run_line("a * 10"), Some("10".to_string()));
+    }
+
+    #[test]
+    fn call_depth_limit_is_configurable() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(20);
+        assert!(interpreter.run(&format!("{}f(18);", RECURSE)).is_ok());
+        assert!(is_stack_overflow(
+            interpreter.run(&format!("{}f(19);", RECURSE))
+        ));
+    }
+
+    #[test]
+    fn only_calls_that_run_a_body_count_towards_the_limit() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(3);
+        let source = "class A {}\nclass B { init() {} }\nfun f() { clock(); A(); return B(); }\n";
+        assert!(interpreter.run(&format!("{}f();", source)).is_ok());
+        assert!(is_stack_overflow(
+            interpreter.run(&format!("{}fun g() {{ f(); }}\ng();", source))
+        ));
+    }
+}
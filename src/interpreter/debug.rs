@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+
+use super::environment::Environment;
+use super::{Interpreter, Object, RuntimeError};
+use crate::ast::Statement;
+use crate::intern::Symbol;
+
+/// Lets a debugger watch a program run. The hook runs on the interpreter's
+/// thread, so the program stays paused for as long as
+/// [`DebugHook::before_statement`] doesn't return.
+pub trait DebugHook {
+    /// Called before each statement, other than blocks, with the line it
+    /// starts on. Breaking stops the program with
+    /// [`RuntimeError::Stopped`].
+    fn before_statement(&mut self, interpreter: &Interpreter, line: usize) -> ControlFlow<()>;
+
+    /// Receives what the program prints, so a debugger that talks to its
+    /// client over stdout can forward it.
+    fn output(&mut self, text: &str) {
+        print!("{}", text);
+    }
+}
+
+/// A call in progress, as shown in a backtrace.
+#[derive(Clone, Debug)]
+pub struct StackFrame {
+    /// The function's name, or `<script>` for the top level.
+    pub name: String,
+    /// The line of the statement the frame is running.
+    pub line: usize,
+}
+
+/// What the interpreter tracks about each call while a hook is installed.
+pub(super) struct Frame {
+    name: Symbol,
+    line: usize,
+    /// The frame's innermost scope as of its last call, which stops being
+    /// current once another frame is pushed on top.
+    environment: Rc<RefCell<Environment>>,
+}
+
+impl Interpreter {
+    /// Installs `hook` and starts tracking calls for it. Install it before
+    /// running anything.
+    pub fn set_debug_hook(&mut self, hook: Box<dyn DebugHook>) {
+        self.debug_hook = Some(hook);
+        self.frames = vec![Frame {
+            name: Symbol::new("<script>"),
+            line: 0,
+            environment: Rc::clone(&self.globals),
+        }];
+    }
+
+    /// The calls in progress, innermost first. Empty without a debug hook.
+    pub fn call_stack(&self) -> Vec<StackFrame> {
+        return self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| StackFrame {
+                name: frame.name.to_string(),
+                line: frame.line,
+            })
+            .collect();
+    }
+
+    /// How many calls are in progress, counting the top level as one.
+    pub fn call_depth(&self) -> usize {
+        return self.frames.len();
+    }
+
+    /// The local variables visible in a frame of [`Interpreter::call_stack`],
+    /// innermost scope first and sorted by name within each scope. Shadowed
+    /// variables are left out.
+    pub fn locals(&self, frame: usize) -> Vec<(Symbol, Object)> {
+        let Some(index) = self.frames.len().checked_sub(frame + 1) else {
+            return Vec::new();
+        };
+        let mut environment = match frame {
+            0 => Rc::clone(&self.environment),
+            _ => Rc::clone(&self.frames[index].environment),
+        };
+        let mut locals: Vec<(Symbol, Object)> = Vec::new();
+        while !Rc::ptr_eq(&environment, &self.globals) {
+            let mut scope: Vec<(Symbol, Object)> = environment
+                .borrow()
+                .values
+                .iter()
+                .filter(|(name, _)| !locals.iter().any(|(seen, _)| seen == *name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            scope.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+            locals.extend(scope);
+
+            let Some(enclosing) = environment.borrow().enclosing.clone() else {
+                break;
+            };
+            environment = enclosing;
+        }
+        return locals;
+    }
+
+    /// Every global variable, sorted by name.
+    pub fn globals(&self) -> Vec<(Symbol, Object)> {
+        let mut globals: Vec<(Symbol, Object)> = self
+            .globals
+            .borrow()
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        globals.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        return globals;
+    }
+
+    /// Hands the statement about to run to the debug hook, if there is one.
+    pub(super) fn debug_statement(&mut self, statement: &Statement) -> Result<(), RuntimeError> {
+        let Some(line) = statement.line() else {
+            return Ok(());
+        };
+        let Some(mut hook) = self.debug_hook.take() else {
+            return Ok(());
+        };
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = line;
+        }
+        let flow = hook.before_statement(self, line);
+        self.debug_hook = Some(hook);
+        match flow {
+            ControlFlow::Continue(()) => return Ok(()),
+            ControlFlow::Break(()) => return Err(RuntimeError::Stopped { line }),
+        }
+    }
+
+    /// Prints a line of program output, through the debug hook if there is
+    /// one.
+    pub(super) fn print(&mut self, text: &str) {
+        match &mut self.debug_hook {
+            Some(hook) => hook.output(&format!("{}\n", text)),
+            None => println!("{}", text),
+        }
+    }
+
+    /// Records a call to the function `name` for the debug hook.
+    pub(super) fn enter_call(&mut self, name: &Symbol) {
+        if self.debug_hook.is_none() {
+            return;
+        }
+        if let Some(caller) = self.frames.last_mut() {
+            caller.environment = Rc::clone(&self.environment);
+        }
+        self.frames.push(Frame {
+            name: name.clone(),
+            line: 0,
+            environment: Rc::clone(&self.environment),
+        });
+    }
+
+    pub(super) fn leave_call(&mut self) {
+        if self.debug_hook.is_some() {
+            self.frames.pop();
+        }
+    }
+}
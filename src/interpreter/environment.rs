@@ -0,0 +1,96 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{InterpreterResult, Object, RuntimeError};
+use crate::intern::Symbol;
+use crate::scanner::Token;
+use crate::table::Table;
+
+pub(super) struct Environment {
+    pub(super) values: Table<Object>,
+    pub(super) enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub(super) fn new() -> Self {
+        Environment {
+            values: Table::new(),
+            enclosing: None,
+        }
+    }
+
+    pub(super) fn new_enclosed(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: Table::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    pub(super) fn define(&mut self, name: Symbol, value: Object) {
+        self.values.insert(name, value);
+    }
+
+    pub(super) fn get(&self, name: &Token) -> InterpreterResult {
+        match self.lookup(&name.lexeme) {
+            Some(value) => return Ok(value),
+            None => return Err(RuntimeError::UndefinedVariable(name.clone())),
+        }
+    }
+
+    pub(super) fn ancestor(
+        environment: &Rc<RefCell<Environment>>,
+        distance: usize,
+    ) -> Rc<RefCell<Environment>> {
+        let mut environment = Rc::clone(environment);
+        for _ in 0..distance {
+            let enclosing = environment.borrow().enclosing.clone();
+            environment = enclosing.expect("resolver computed a depth past the global scope");
+        }
+        return environment;
+    }
+
+    pub(super) fn get_at(
+        environment: &Rc<RefCell<Environment>>,
+        distance: usize,
+        name: &Symbol,
+    ) -> Option<Object> {
+        return Environment::ancestor(environment, distance)
+            .borrow()
+            .values
+            .get(name)
+            .cloned();
+    }
+
+    pub(super) fn assign_at(
+        environment: &Rc<RefCell<Environment>>,
+        distance: usize,
+        name: &Token,
+        value: Object,
+    ) {
+        Environment::ancestor(environment, distance)
+            .borrow_mut()
+            .values
+            .insert(name.lexeme.clone(), value);
+    }
+
+    pub(super) fn lookup(&self, name: &Symbol) -> Option<Object> {
+        if let Some(value) = self.values.get(name) {
+            return Some(value.clone());
+        }
+        match &self.enclosing {
+            Some(enclosing) => return enclosing.borrow().lookup(name),
+            None => return None,
+        }
+    }
+
+    pub(super) fn assign(&mut self, name: &Token, value: Object) -> Result<(), RuntimeError> {
+        if let Some(slot) = self.values.get_mut(&name.lexeme) {
+            *slot = value;
+            return Ok(());
+        }
+        if let Some(enclosing) = &self.enclosing {
+            return enclosing.borrow_mut().assign(name, value);
+        }
+        return Err(RuntimeError::UndefinedVariable(name.clone()));
+    }
+}
@@ -0,0 +1,309 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::environment::Environment;
+use super::{Interpreter, InterpreterResult, RuntimeError, Unwind};
+use crate::ast::FunctionDeclaration;
+use crate::intern::Symbol;
+use crate::scanner::Token;
+use crate::table::Table;
+
+#[derive(Clone)]
+pub enum Object {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Symbol),
+    Function(Rc<LoxFunction>),
+    NativeFunction(Rc<NativeFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::Nil, Object::Nil) => true,
+            (Object::Boolean(a), Object::Boolean(b)) => a == b,
+            (Object::Number(a), Object::Number(b)) => a == b,
+            (Object::String(a), Object::String(b)) => a == b,
+            (Object::Function(a), Object::Function(b)) => Rc::ptr_eq(a, b),
+            (Object::NativeFunction(a), Object::NativeFunction(b)) => Rc::ptr_eq(a, b),
+            (Object::Class(a), Object::Class(b)) => Rc::ptr_eq(a, b),
+            (Object::Instance(a), Object::Instance(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Object {
+    /// The name used for this value's type in conversion errors.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Nil => "nil",
+            Object::Boolean(_) => "boolean",
+            Object::Number(_) => "number",
+            Object::String(_) => "string",
+            Object::Function(_) | Object::NativeFunction(_) => "function",
+            Object::Class(_) => "class",
+            Object::Instance(_) => "instance",
+        }
+    }
+}
+
+impl From<()> for Object {
+    fn from(_: ()) -> Self {
+        Object::Nil
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Self {
+        Object::Boolean(value)
+    }
+}
+
+impl From<f64> for Object {
+    fn from(value: f64) -> Self {
+        Object::Number(value)
+    }
+}
+
+impl From<String> for Object {
+    fn from(value: String) -> Self {
+        Object::String(Symbol::new(&value))
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Self {
+        Object::String(Symbol::new(value))
+    }
+}
+
+impl<T: Into<Object>> From<Option<T>> for Object {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => value.into(),
+            None => Object::Nil,
+        }
+    }
+}
+
+impl TryFrom<&Object> for bool {
+    type Error = String;
+
+    fn try_from(value: &Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Boolean(b) => return Ok(*b),
+            _ => return Err(format!("Expected a boolean but got {}.", value.type_name())),
+        }
+    }
+}
+
+impl TryFrom<&Object> for f64 {
+    type Error = String;
+
+    fn try_from(value: &Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Number(n) => return Ok(*n),
+            _ => return Err(format!("Expected a number but got {}.", value.type_name())),
+        }
+    }
+}
+
+impl TryFrom<&Object> for String {
+    type Error = String;
+
+    fn try_from(value: &Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::String(s) => return Ok(s.to_string()),
+            _ => return Err(format!("Expected a string but got {}.", value.type_name())),
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Object::Nil => write!(f, "nil"),
+            Object::Boolean(b) => write!(f, "{}", b),
+            Object::Number(n) => write!(f, "{}", n),
+            Object::String(s) => write!(f, "{}", s),
+            Object::Function(function) => write!(f, "<fn {}>", function.declaration.name.lexeme),
+            Object::NativeFunction(_) => write!(f, "<native fn>"),
+            Object::Class(class) => write!(f, "{}", class.name),
+            Object::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
+        }
+    }
+}
+
+pub trait LoxCallable {
+    fn arity(&self) -> usize;
+    /// `paren` is the closing parenthesis of the call site, used to locate
+    /// errors raised from inside the callee.
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter,
+        paren: &Token,
+        arguments: Vec<Object>,
+    ) -> InterpreterResult;
+}
+
+pub struct LoxFunction {
+    pub(super) declaration: Rc<FunctionDeclaration>,
+    pub(super) closure: Rc<RefCell<Environment>>,
+    pub(super) is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub(super) fn bind(&self, instance: Rc<RefCell<LoxInstance>>) -> LoxFunction {
+        let mut environment = Environment::new_enclosed(Rc::clone(&self.closure));
+        environment.define(Symbol::new("this"), Object::Instance(instance));
+        LoxFunction {
+            declaration: Rc::clone(&self.declaration),
+            closure: Rc::new(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    fn this(&self) -> Object {
+        let closure = self.closure.borrow();
+        return closure.values.get(&Symbol::new("this")).unwrap().clone();
+    }
+}
+
+impl LoxCallable for LoxFunction {
+    fn arity(&self) -> usize {
+        return self.declaration.params.len();
+    }
+
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter,
+        _paren: &Token,
+        arguments: Vec<Object>,
+    ) -> InterpreterResult {
+        let mut environment = Environment::new_enclosed(Rc::clone(&self.closure));
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            environment.define(param.lexeme.clone(), argument);
+        }
+
+        let environment = Rc::new(RefCell::new(environment));
+        interpreter.call_depth += 1;
+        interpreter.enter_call(&self.declaration.name.lexeme);
+        let result = interpreter.execute_block(&self.declaration.body, environment);
+        interpreter.leave_call();
+        interpreter.call_depth -= 1;
+        match result {
+            Ok(()) | Err(Unwind::Return(_)) if self.is_initializer => return Ok(self.this()),
+            Ok(()) => return Ok(Object::Nil),
+            Err(Unwind::Return(value)) => return Ok(value),
+            Err(Unwind::Error(error)) => return Err(error),
+        }
+    }
+}
+
+/// The body of a host-defined function. An `Err` message becomes a runtime
+/// error reported at the call site.
+pub type NativeFn = dyn Fn(&[Object]) -> Result<Object, String>;
+
+pub struct NativeFunction {
+    pub(super) arity: usize,
+    pub(super) function: Box<NativeFn>,
+}
+
+impl LoxCallable for NativeFunction {
+    fn arity(&self) -> usize {
+        return self.arity;
+    }
+
+    fn call(
+        self: Rc<Self>,
+        _interpreter: &mut Interpreter,
+        paren: &Token,
+        arguments: Vec<Object>,
+    ) -> InterpreterResult {
+        return (self.function)(&arguments).map_err(|message| RuntimeError::Native {
+            paren: paren.clone(),
+            message,
+        });
+    }
+}
+
+pub struct LoxClass {
+    pub(super) name: Symbol,
+    pub(super) superclass: Option<Rc<LoxClass>>,
+    pub(super) methods: Table<Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    pub(super) fn find_method(&self, name: &Symbol) -> Option<Rc<LoxFunction>> {
+        if let Some(method) = self.methods.get(name) {
+            return Some(Rc::clone(method));
+        }
+        match &self.superclass {
+            Some(superclass) => return superclass.find_method(name),
+            None => return None,
+        }
+    }
+}
+
+impl LoxCallable for LoxClass {
+    fn arity(&self) -> usize {
+        match self.find_method(&Symbol::new("init")) {
+            Some(initializer) => return initializer.arity(),
+            None => return 0,
+        }
+    }
+
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter,
+        paren: &Token,
+        arguments: Vec<Object>,
+    ) -> InterpreterResult {
+        let instance = Rc::new(RefCell::new(LoxInstance {
+            class: Rc::clone(&self),
+            fields: Table::new(),
+        }));
+        if let Some(initializer) = self.find_method(&Symbol::new("init")) {
+            Rc::new(initializer.bind(Rc::clone(&instance))).call(interpreter, paren, arguments)?;
+        }
+        return Ok(Object::Instance(instance));
+    }
+}
+
+pub struct LoxInstance {
+    pub(super) class: Rc<LoxClass>,
+    pub(super) fields: Table<Object>,
+}
+
+impl LoxInstance {
+    pub(super) fn get(instance: &Rc<RefCell<LoxInstance>>, name: &Token) -> InterpreterResult {
+        if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(&name.lexeme);
+        if let Some(method) = method {
+            let bound = method.bind(Rc::clone(instance));
+            return Ok(Object::Function(Rc::new(bound)));
+        }
+
+        return Err(RuntimeError::UndefinedProperty(name.clone()));
+    }
+
+    pub(super) fn set(&mut self, name: &Token, value: Object) {
+        self.fields.insert(name.lexeme.clone(), value);
+    }
+}
+
+pub(crate) fn clock(_arguments: &[Object]) -> Result<Object, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    return Ok(Object::Number(now.as_secs_f64()));
+}
@@ -0,0 +1,263 @@
+use serde_json::{json, Value};
+
+use crate::ast::Expression;
+use crate::scanner::{Span, Token, TokenType};
+use crate::LoxError;
+
+/// Each token as an object with its type, lexeme, literal value, line and
+/// span.
+pub fn tokens(tokens: &[Token]) -> Value {
+    return tokens.iter().map(token).collect();
+}
+
+pub fn token(token: &Token) -> Value {
+    return json!({
+        "type": token._type.to_string(),
+        "lexeme": token.lexeme.as_str(),
+        "literal": literal(&token._type),
+        "line": token.line,
+        "column": token.column,
+        "span": span(token.span),
+    });
+}
+
+/// The syntax tree as nested objects. Every node has a `kind` naming its
+/// variant and the `span` of source it covers.
+pub fn expression(expression: &Expression) -> Value {
+    let mut node = match expression {
+        Expression::Literal(token) => json!({
+            "kind": "Literal",
+            "value": value(&token._type),
+        }),
+        Expression::Unary(operator, right) => json!({
+            "kind": "Unary",
+            "operator": operator.lexeme.as_str(),
+            "right": self::expression(right),
+        }),
+        Expression::Binary(left, operator, right) => json!({
+            "kind": "Binary",
+            "operator": operator.lexeme.as_str(),
+            "left": self::expression(left),
+            "right": self::expression(right),
+        }),
+        Expression::Grouping(inner) => json!({
+            "kind": "Grouping",
+            "expression": self::expression(inner),
+        }),
+        Expression::Variable(_, name) => json!({
+            "kind": "Variable",
+            "name": name.lexeme.as_str(),
+        }),
+        Expression::Assign(_, name, value) => json!({
+            "kind": "Assign",
+            "name": name.lexeme.as_str(),
+            "value": self::expression(value),
+        }),
+        Expression::Logical(left, operator, right) => json!({
+            "kind": "Logical",
+            "operator": operator.lexeme.as_str(),
+            "left": self::expression(left),
+            "right": self::expression(right),
+        }),
+        Expression::Call(callee, _, arguments) => json!({
+            "kind": "Call",
+            "callee": self::expression(callee),
+            "arguments": arguments.iter().map(self::expression).collect::<Value>(),
+        }),
+        Expression::Get(object, name) => json!({
+            "kind": "Get",
+            "object": self::expression(object),
+            "name": name.lexeme.as_str(),
+        }),
+        Expression::Set(object, name, value) => json!({
+            "kind": "Set",
+            "object": self::expression(object),
+            "name": name.lexeme.as_str(),
+            "value": self::expression(value),
+        }),
+        Expression::This(..) => json!({ "kind": "This" }),
+        Expression::Super(_, _, method) => json!({
+            "kind": "Super",
+            "method": method.lexeme.as_str(),
+        }),
+    };
+    node["span"] = span(expression.span());
+    return node;
+}
+
+/// Every error as an object with its `kind` (`compile` or `runtime`), what
+/// went wrong, and where it points.
+pub fn diagnostics(error: &LoxError) -> Value {
+    match error {
+        LoxError::Compile(errors) => {
+            return errors
+                .iter()
+                .map(|error| diagnostic("compile", error.message(), error.location()))
+                .collect();
+        }
+        LoxError::Runtime(error) => {
+            return json!([diagnostic("runtime", error.message(), error.location())]);
+        }
+    }
+}
+
+fn diagnostic(
+    kind: &str,
+    message: String,
+    (line, column, location): (usize, usize, Span),
+) -> Value {
+    return json!({
+        "kind": kind,
+        "message": message,
+        "line": line,
+        "column": column,
+        "span": span(location),
+    });
+}
+
+/// The literal a token carries, as in the text output: only strings and
+/// numbers have one. JSON has no infinity, so a number literal too large
+/// for a double is given as its text, `"inf"`.
+fn literal(_type: &TokenType) -> Value {
+    match _type {
+        TokenType::StringLiteral(value) => return json!(value.as_str()),
+        TokenType::Number(value) if value.is_finite() => return json!(value),
+        TokenType::Number(value) => return json!(value.to_string()),
+        _ => return Value::Null,
+    }
+}
+
+/// The value a literal expression evaluates to.
+fn value(_type: &TokenType) -> Value {
+    match _type {
+        TokenType::True => return json!(true),
+        TokenType::False => return json!(false),
+        _ => return literal(_type),
+    }
+}
+
+fn span(span: Span) -> Value {
+    return json!({ "start": span.start, "end": span.end });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, Parser};
+
+    #[test]
+    fn tokens_have_a_type_lexeme_literal_and_location() {
+        let tokens = crate::scan("print \"hi\" 1.5;").unwrap();
+        assert_eq!(
+            self::tokens(&tokens),
+            json!([
+                {
+                    "type": "PRINT",
+                    "lexeme": "print",
+                    "literal": null,
+                    "line": 1,
+                    "column": 1,
+                    "span": { "start": 0, "end": 5 },
+                },
+                {
+                    "type": "STRING",
+                    "lexeme": "\"hi\"",
+                    "literal": "hi",
+                    "line": 1,
+                    "column": 7,
+                    "span": { "start": 6, "end": 10 },
+                },
+                {
+                    "type": "NUMBER",
+                    "lexeme": "1.5",
+                    "literal": 1.5,
+                    "line": 1,
+                    "column": 12,
+                    "span": { "start": 11, "end": 14 },
+                },
+                {
+                    "type": "SEMICOLON",
+                    "lexeme": ";",
+                    "literal": null,
+                    "line": 1,
+                    "column": 15,
+                    "span": { "start": 14, "end": 15 },
+                },
+                {
+                    "type": "EOF",
+                    "lexeme": "",
+                    "literal": null,
+                    "line": 1,
+                    "column": 16,
+                    "span": { "start": 15, "end": 15 },
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn overflowing_numbers_keep_their_text() {
+        let tokens = crate::scan(&"9".repeat(400)).unwrap();
+        assert_eq!(token(&tokens[0])["literal"], json!("inf"));
+    }
+
+    #[test]
+    fn expressions_nest_with_their_kind_and_span() {
+        let tokens = crate::scan("(1 + x) * -2").unwrap();
+        let expression = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            self::expression(&expression),
+            json!({
+                "kind": "Binary",
+                "operator": "*",
+                "left": {
+                    "kind": "Grouping",
+                    "expression": {
+                        "kind": "Binary",
+                        "operator": "+",
+                        "left": { "kind": "Literal", "value": 1.0, "span": { "start": 1, "end": 2 } },
+                        "right": { "kind": "Variable", "name": "x", "span": { "start": 5, "end": 6 } },
+                        "span": { "start": 1, "end": 6 },
+                    },
+                    "span": { "start": 1, "end": 6 },
+                },
+                "right": {
+                    "kind": "Unary",
+                    "operator": "-",
+                    "right": { "kind": "Literal", "value": 2.0, "span": { "start": 11, "end": 12 } },
+                    "span": { "start": 10, "end": 12 },
+                },
+                "span": { "start": 1, "end": 12 },
+            })
+        );
+    }
+
+    #[test]
+    fn diagnostics_have_a_kind_message_and_location() {
+        let Err(error) = crate::parse_program("print 1;\nprint (2;") else {
+            panic!("expected a syntax error");
+        };
+        assert_eq!(
+            diagnostics(&error),
+            json!([{
+                "kind": "compile",
+                "message": "Expect ')' after expression",
+                "line": 2,
+                "column": 9,
+                "span": { "start": 17, "end": 18 },
+            }])
+        );
+
+        let error = Interpreter::new().run("print 1;\nprint -nil;").unwrap_err();
+        assert_eq!(
+            diagnostics(&error),
+            json!([{
+                "kind": "runtime",
+                "message": "Operand must be a number.",
+                "line": 2,
+                "column": 7,
+                "span": { "start": 15, "end": 19 },
+            }])
+        );
+    }
+}
@@ -0,0 +1,136 @@
+#![allow(clippy::needless_return)]
+//! An interpreter for the Lox language.
+//!
+//! The pipeline is [`Scanner`] → [`Parser`] → [`Resolver`] → [`Interpreter`];
+//! [`Interpreter::run`] drives all of it for a complete program. [`Vm::run`]
+//! is an alternative backend that compiles the resolved AST to bytecode.
+
+pub mod ast;
+pub mod cst;
+pub mod debugger;
+pub mod diagnostic;
+pub mod formatter;
+pub mod intern;
+pub mod interpreter;
+pub mod json;
+pub mod lsp;
+pub mod optimizer;
+pub mod parser;
+mod protocol;
+pub mod resolver;
+pub mod scanner;
+pub mod table;
+pub mod vm;
+
+use std::fmt;
+
+use thiserror::Error;
+
+pub use crate::ast::{Expression, FunctionDeclaration, Statement};
+pub use crate::intern::Symbol;
+pub use crate::interpreter::{Interpreter, Object, RuntimeError};
+pub use crate::parser::{ParseError, Parser};
+pub use crate::resolver::Resolver;
+pub use crate::scanner::{ScanError, Scanner, Span, Token, TokenType};
+pub use crate::table::Table;
+pub use crate::vm::Vm;
+
+/// Everything that can stop a program, mapped to the exit code it reports with.
+#[derive(Debug, Error)]
+pub enum LoxError {
+    #[error("{}", join_lines(.0))]
+    Compile(Vec<ParseError>),
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+}
+
+impl LoxError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            LoxError::Compile(_) => 65,
+            LoxError::Runtime(_) => 70,
+        }
+    }
+}
+
+fn join_lines<T: fmt::Display>(errors: &[T]) -> String {
+    return errors
+        .iter()
+        .map(|error| error.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+}
+
+/// Scans `source`, failing if any character could not be tokenized.
+pub fn scan(source: &str) -> Result<Vec<Token>, LoxError> {
+    let (tokens, errors) = Scanner::scan(source.to_string());
+    if !errors.is_empty() {
+        return Err(LoxError::Compile(
+            errors.into_iter().map(ParseError::from).collect(),
+        ));
+    }
+    return Ok(tokens);
+}
+
+/// Scans and parses `source` as a single expression.
+pub fn parse_expression(source: &str) -> Result<Expression, LoxError> {
+    let tokens = scan(source)?;
+    return Parser::new(tokens)
+        .parse()
+        .map_err(|error| LoxError::Compile(vec![error]));
+}
+
+/// Scans and parses `source` as a program, reporting every scan and syntax
+/// error rather than stopping at the first.
+pub fn parse_program(source: &str) -> Result<Vec<Statement>, LoxError> {
+    let (tokens, scan_errors) = Scanner::scan(source.to_string());
+    let mut errors: Vec<ParseError> = scan_errors.into_iter().map(ParseError::from).collect();
+
+    match Parser::new(tokens).parse_program() {
+        Ok(statements) if errors.is_empty() => return Ok(statements),
+        Ok(_) => return Err(LoxError::Compile(errors)),
+        Err(parse_errors) => {
+            errors.extend(parse_errors);
+            return Err(LoxError::Compile(errors));
+        }
+    }
+}
+
+/// Whether `source` stops partway through a construct: a brace or parenthesis
+/// is still open, or a string literal runs past the end.
+pub fn is_incomplete(source: &str) -> bool {
+    let (tokens, errors) = Scanner::scan(source.to_string());
+    let mut depth = 0;
+    for token in tokens {
+        match token._type {
+            TokenType::LeftBrace | TokenType::LeftParen => depth += 1,
+            TokenType::RightBrace | TokenType::RightParen => depth -= 1,
+            _ => {}
+        }
+    }
+    let unterminated = errors
+        .iter()
+        .any(|error| matches!(error, ScanError::UnterminatedString { .. }));
+    return depth > 0 || unterminated;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_is_incomplete_while_a_brace_or_string_is_open() {
+        assert!(is_incomplete("fun f() {"));
+        assert!(is_incomplete("if (true) { print (1 +"));
+        assert!(is_incomplete("print \"unterminated"));
+        assert!(!is_incomplete("fun f() { print 1; }"));
+        assert!(!is_incomplete("print 1 +"));
+    }
+
+    #[test]
+    fn braces_in_strings_and_comments_dont_count() {
+        assert!(!is_incomplete("print \"{\";"));
+        assert!(!is_incomplete("print 1; // {"));
+        assert!(is_incomplete("{ print \"}\"; // }"));
+    }
+}
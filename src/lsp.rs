@@ -0,0 +1,517 @@
+mod analysis;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+use self::analysis::{outline, Analysis, DefinitionKind, OutlineItem};
+use crate::cst;
+use crate::interpreter::Interpreter;
+use crate::parser::ParseError;
+use crate::protocol::{read_message, write_message};
+use crate::resolver::Resolver;
+use crate::scanner::{Span, KEYWORDS};
+use crate::LoxError;
+
+/// JSON-RPC error codes from the protocol.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Serves the Language Server Protocol over `input` and `output` until the
+/// client sends `exit` or closes `input`. Returns the exit code the protocol
+/// asks for: 0 if the client shut the server down first, 1 otherwise.
+pub fn serve(mut input: impl BufRead, output: impl Write) -> io::Result<i32> {
+    let mut server = Server {
+        output,
+        documents: HashMap::new(),
+        shut_down: false,
+    };
+    while let Some(body) = read_message(&mut input)? {
+        let message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(error) => {
+                server.respond(Value::Null, Err((PARSE_ERROR, error.to_string())))?;
+                continue;
+            }
+        };
+        if let Some(code) = server.handle(message)? {
+            return Ok(code);
+        }
+    }
+    return Ok(if server.shut_down { 0 } else { 1 });
+}
+
+/// An open file, analysed again whenever it changes.
+struct Document {
+    text: String,
+    lines: LineIndex,
+    analysis: Analysis,
+    outline: Vec<OutlineItem>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let tree = cst::parse(&text);
+        Document {
+            lines: LineIndex::new(&text),
+            analysis: Analysis::new(&tree),
+            outline: outline(&tree),
+            text,
+        }
+    }
+
+    fn range(&self, span: Span) -> Value {
+        return json!({
+            "start": self.lines.position(&self.text, span.start),
+            "end": self.lines.position(&self.text, span.end),
+        });
+    }
+}
+
+/// Converts byte offsets to the protocol's positions, which count lines from
+/// zero and characters in UTF-16 code units, and back.
+struct LineIndex {
+    /// The offset each line starts at.
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(text.match_indices('\n').map(|(index, _)| index + 1));
+        LineIndex { starts }
+    }
+
+    fn position(&self, text: &str, offset: usize) -> Value {
+        let offset = offset.min(text.len());
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let character = text[self.starts[line]..offset].encode_utf16().count();
+        return json!({ "line": line, "character": character });
+    }
+
+    fn offset(&self, text: &str, position: &Value) -> usize {
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let Some(&start) = self.starts.get(line) else {
+            return text.len();
+        };
+        let end = self.starts.get(line + 1).copied().unwrap_or(text.len());
+        let mut remaining = position["character"].as_u64().unwrap_or(0) as usize;
+        for (index, character) in text[start..end].char_indices() {
+            if remaining == 0 || character == '\n' {
+                return start + index;
+            }
+            remaining = remaining.saturating_sub(character.len_utf16());
+        }
+        return end;
+    }
+}
+
+type Response = Result<Value, (i64, String)>;
+
+struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Document>,
+    shut_down: bool,
+}
+
+impl<W: Write> Server<W> {
+    /// Handles one message, returning the exit code once the client asks the
+    /// server to exit.
+    fn handle(&mut self, message: Value) -> io::Result<Option<i32>> {
+        let Some(method) = message["method"].as_str() else {
+            // A response to a request the server never sends.
+            return Ok(None);
+        };
+        let params = &message["params"];
+        match message.get("id") {
+            Some(id) => {
+                let response = if self.shut_down {
+                    Err((INVALID_REQUEST, "The server is shut down.".to_string()))
+                } else {
+                    self.request(method, params)
+                };
+                self.respond(id.clone(), response)?;
+            }
+            None if method == "exit" => return Ok(Some(if self.shut_down { 0 } else { 1 })),
+            None => self.notification(method, params)?,
+        }
+        return Ok(None);
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Response {
+        match method {
+            "initialize" => return Ok(capabilities()),
+            "shutdown" => {
+                self.shut_down = true;
+                return Ok(Value::Null);
+            }
+            "textDocument/definition" => return Ok(self.definition(params)),
+            "textDocument/references" => return Ok(self.references(params)),
+            "textDocument/hover" => return Ok(self.hover(params)),
+            "textDocument/documentSymbol" => return Ok(self.document_symbols(params)),
+            "textDocument/completion" => return Ok(self.completion(params)),
+            _ => return Err((METHOD_NOT_FOUND, format!("Unknown method {}.", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> io::Result<()> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.update(uri, text.to_string())?;
+            }
+            "textDocument/didChange" => {
+                // The server asks for full text sync, so the last change is
+                // the whole document.
+                if let Some(change) = params["contentChanges"].as_array().and_then(|c| c.last()) {
+                    let text = change["text"].as_str().unwrap_or_default();
+                    self.update(uri, text.to_string())?;
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.notify(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )?;
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> io::Result<()> {
+        let document = Document::new(text);
+        let diagnostics: Vec<Value> = check(&document.text)
+            .iter()
+            .map(|error| {
+                json!({
+                    "range": document.range(error.location().2),
+                    "severity": 1,
+                    "source": "lox",
+                    "message": error.message(),
+                })
+            })
+            .collect();
+        self.documents.insert(uri.to_string(), document);
+        return self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        );
+    }
+
+    /// The document a request is about and the offset of its position.
+    fn locate<'a>(&'a self, params: &'a Value) -> Option<(&'a str, &'a Document, usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let document = self.documents.get(uri)?;
+        let offset = document.lines.offset(&document.text, &params["position"]);
+        return Some((uri, document, offset));
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let Some((uri, document, offset)) = self.locate(params) else {
+            return Value::Null;
+        };
+        let Some((index, _)) = document.analysis.definition_at(offset) else {
+            return Value::Null;
+        };
+        let definition = &document.analysis.definitions[index];
+        return json!({ "uri": uri, "range": document.range(definition.name_span) });
+    }
+
+    fn references(&self, params: &Value) -> Value {
+        let Some((uri, document, offset)) = self.locate(params) else {
+            return json!([]);
+        };
+        let Some((index, _)) = document.analysis.definition_at(offset) else {
+            return json!([]);
+        };
+        let mut spans = document.analysis.references_to(index);
+        if params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(true)
+        {
+            spans.insert(0, document.analysis.definitions[index].name_span);
+        }
+        return spans
+            .into_iter()
+            .map(|span| json!({ "uri": uri, "range": document.range(span) }))
+            .collect();
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((_, document, offset)) = self.locate(params) else {
+            return Value::Null;
+        };
+        let Some((index, span)) = document.analysis.definition_at(offset) else {
+            return Value::Null;
+        };
+        let definition = &document.analysis.definitions[index];
+        let mut contents = format!("```lox\n{}\n```", definition.signature);
+        if let Some(documentation) = &definition.documentation {
+            contents += &format!("\n\n{}", documentation);
+        }
+        return json!({
+            "contents": { "kind": "markdown", "value": contents },
+            "range": document.range(span),
+        });
+    }
+
+    fn document_symbols(&self, params: &Value) -> Value {
+        let Some(document) = params["textDocument"]["uri"]
+            .as_str()
+            .and_then(|uri| self.documents.get(uri))
+        else {
+            return json!([]);
+        };
+        return document
+            .outline
+            .iter()
+            .map(|item| document_symbol(document, item))
+            .collect();
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let mut items: Vec<Value> = KEYWORDS
+            .iter()
+            .map(|keyword| json!({ "label": keyword, "kind": 14 }))
+            .collect();
+        if let Some((_, document, offset)) = self.locate(params) {
+            for definition in document.analysis.visible_at(offset) {
+                let kind = match definition.kind {
+                    DefinitionKind::Variable | DefinitionKind::Parameter => 6,
+                    DefinitionKind::Function | DefinitionKind::Method => 3,
+                    DefinitionKind::Class => 7,
+                };
+                items.push(json!({
+                    "label": definition.name,
+                    "kind": kind,
+                    "detail": definition.signature,
+                }));
+            }
+        }
+        return Value::Array(items);
+    }
+
+    fn respond(&mut self, id: Value, response: Response) -> io::Result<()> {
+        let message = match response {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        return self.send(&message);
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        return self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn send(&mut self, message: &Value) -> io::Result<()> {
+        return write_message(&mut self.output, message);
+    }
+}
+
+fn capabilities() -> Value {
+    return json!({
+        "capabilities": {
+            "textDocumentSync": { "openClose": true, "change": 1 },
+            "definitionProvider": true,
+            "referencesProvider": true,
+            "hoverProvider": true,
+            "documentSymbolProvider": true,
+            "completionProvider": {},
+        },
+        "serverInfo": { "name": "lox", "version": env!("CARGO_PKG_VERSION") },
+    });
+}
+
+/// Every error the scanner, parser and resolver find in `source`.
+fn check(source: &str) -> Vec<ParseError> {
+    match crate::parse_program(source) {
+        Ok(statements) => {
+            let mut interpreter = Interpreter::new();
+            return Resolver::new(&mut interpreter)
+                .resolve(&statements)
+                .err()
+                .unwrap_or_default();
+        }
+        Err(LoxError::Compile(errors)) => return errors,
+        Err(LoxError::Runtime(_)) => unreachable!("parsing doesn't run anything"),
+    }
+}
+
+fn document_symbol(document: &Document, item: &OutlineItem) -> Value {
+    let kind = match item.kind {
+        DefinitionKind::Class => 5,
+        DefinitionKind::Method => 6,
+        DefinitionKind::Function => 12,
+        DefinitionKind::Variable | DefinitionKind::Parameter => 13,
+    };
+    return json!({
+        "name": item.name,
+        "kind": kind,
+        "range": document.range(item.span),
+        "selectionRange": document.range(item.name_span),
+        "children": item
+            .children
+            .iter()
+            .map(|child| document_symbol(document, child))
+            .collect::<Vec<Value>>(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        let text = "var é = \"😀\";\nprint é;\n";
+        let lines = LineIndex::new(text);
+        let semicolon = text.find(';').unwrap();
+        assert_eq!(
+            lines.position(text, semicolon),
+            json!({ "line": 0, "character": 12 })
+        );
+        let print = text.find("print").unwrap();
+        assert_eq!(
+            lines.position(text, print + 6),
+            json!({ "line": 1, "character": 6 })
+        );
+        assert_eq!(
+            lines.position(text, text.len()),
+            json!({ "line": 2, "character": 0 })
+        );
+
+        for offset in [0, 4, 6, semicolon, print + 6, text.len()] {
+            assert_eq!(lines.offset(text, &lines.position(text, offset)), offset);
+        }
+        // A position inside the surrogate pair lands after the character, and
+        // one past the end of a line stops at its end.
+        let emoji = text.find('😀').unwrap();
+        let middle = json!({ "line": 0, "character": 10 });
+        assert_eq!(lines.offset(text, &middle), emoji + '😀'.len_utf8());
+        let past_end = json!({ "line": 1, "character": 50 });
+        assert_eq!(lines.offset(text, &past_end), text.len() - 1);
+    }
+
+    fn message(message: Value) -> Vec<u8> {
+        let mut framed = Vec::new();
+        write_message(&mut framed, &message).unwrap();
+        return framed;
+    }
+
+    /// Runs a session of `messages` and returns the exit code and every
+    /// message the server sent.
+    fn session(messages: &[Value]) -> (i32, Vec<Value>) {
+        let input: Vec<u8> = messages
+            .iter()
+            .flat_map(|m| self::message(m.clone()))
+            .collect();
+        let mut output = Vec::new();
+        let code = serve(Cursor::new(input), &mut output).unwrap();
+        let mut output = Cursor::new(output);
+        let mut sent = Vec::new();
+        while let Some(body) = read_message(&mut output).unwrap() {
+            sent.push(serde_json::from_slice(&body).unwrap());
+        }
+        return (code, sent);
+    }
+
+    fn open(uri: &str, text: &str) -> Value {
+        return json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": { "uri": uri, "languageId": "lox", "version": 1, "text": text },
+            },
+        });
+    }
+
+    #[test]
+    fn serves_definitions_after_initialize_and_open() {
+        let uri = "file:///test.lox";
+        let (code, sent) = session(&[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+            open(uri, "var count = 1;\nprint count;\n"),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "textDocument/definition",
+                "params": {
+                    "textDocument": { "uri": uri },
+                    "position": { "line": 1, "character": 8 },
+                },
+            }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ]);
+
+        assert_eq!(code, 0);
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[0]["id"], 1);
+        assert_eq!(
+            sent[0]["result"]["capabilities"]["definitionProvider"],
+            true
+        );
+        assert_eq!(
+            sent[1],
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": uri, "diagnostics": [] },
+            })
+        );
+        assert_eq!(
+            sent[2],
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "result": {
+                    "uri": uri,
+                    "range": {
+                        "start": { "line": 0, "character": 4 },
+                        "end": { "line": 0, "character": 9 },
+                    },
+                },
+            })
+        );
+        assert_eq!(
+            sent[3],
+            json!({ "jsonrpc": "2.0", "id": 3, "result": null })
+        );
+    }
+
+    #[test]
+    fn crlf_documents_only_report_real_errors() {
+        let uri = "file:///windows.lox";
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "var a = 1;\r\nprint a;\r\nprint b\r\n" }],
+            },
+        });
+        let (code, sent) = session(&[open(uri, "var a = 1;\r\nprint a;\r\n"), change]);
+
+        assert_eq!(code, 1);
+        assert_eq!(sent[0]["params"]["diagnostics"], json!([]));
+        let diagnostics = sent[1]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["message"], "Expect ';' after value.");
+        assert_eq!(
+            diagnostics[0]["range"]["start"],
+            json!({ "line": 3, "character": 0 })
+        );
+    }
+}
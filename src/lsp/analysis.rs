@@ -0,0 +1,545 @@
+use crate::cst::{SyntaxElement, SyntaxKind, SyntaxNode};
+use crate::scanner::{Span, SyntaxToken, TokenType, TriviaKind};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum DefinitionKind {
+    Variable,
+    Parameter,
+    Function,
+    /// Only in the outline, since methods aren't looked up by name.
+    Method,
+    Class,
+}
+
+/// A name some declaration introduces.
+pub(super) struct Definition {
+    pub(super) name: String,
+    pub(super) kind: DefinitionKind,
+    /// Where the name is written in the declaration.
+    pub(super) name_span: Span,
+    /// The scope the name is visible in, which is the whole program for
+    /// globals.
+    pub(super) scope: Span,
+    /// The declaration as hover shows it, like `fun add(a, b)`.
+    pub(super) signature: String,
+    /// The `//` comments directly above the declaration.
+    pub(super) documentation: Option<String>,
+}
+
+/// Resolves every variable, function and class name in a syntax tree to the
+/// declaration it refers to, following the same scoping rules as the
+/// [`Resolver`](crate::Resolver). Works from the lossless tree, so files
+/// with syntax errors still get as much as can be made out of them.
+pub(super) struct Analysis {
+    pub(super) definitions: Vec<Definition>,
+    /// Every use of a name that could be resolved, with its definition.
+    pub(super) references: Vec<(Span, usize)>,
+}
+
+impl Analysis {
+    pub(super) fn new(tree: &SyntaxNode) -> Self {
+        let mut walker = Walker {
+            definitions: Vec::new(),
+            references: Vec::new(),
+            scopes: Vec::new(),
+            globals: Vec::new(),
+            functions: 0,
+        };
+        walker.scoped(everything(), |walker| walker.children(tree));
+        walker.resolve_globals();
+        return Analysis {
+            definitions: walker.definitions,
+            references: walker.references,
+        };
+    }
+
+    /// The definition of the name at `offset`, which may be the declaration
+    /// itself or a use of it. A cursor right after a name still counts.
+    pub(super) fn definition_at(&self, offset: usize) -> Option<(usize, Span)> {
+        let declared = self
+            .definitions
+            .iter()
+            .enumerate()
+            .map(|(index, definition)| (definition.name_span, index));
+        return self
+            .references
+            .iter()
+            .copied()
+            .chain(declared)
+            .find(|(span, _)| span.start <= offset && offset <= span.end)
+            .map(|(span, index)| (index, span));
+    }
+
+    /// The spans of every use of a definition, in source order.
+    pub(super) fn references_to(&self, definition: usize) -> Vec<Span> {
+        return self
+            .references
+            .iter()
+            .filter(|(_, index)| *index == definition)
+            .map(|(span, _)| *span)
+            .collect();
+    }
+
+    /// The names in scope at `offset`: locals declared before it and every
+    /// global, since functions may use globals declared after them. A local
+    /// shadows anything else with its name.
+    pub(super) fn visible_at(&self, offset: usize) -> Vec<&Definition> {
+        let mut visible: Vec<&Definition> = Vec::new();
+        for definition in self.definitions.iter().rev() {
+            let in_scope = definition.scope.start <= offset && offset <= definition.scope.end;
+            let is_global = definition.scope == everything();
+            let declared = is_global || definition.name_span.end < offset;
+            if in_scope && declared && !visible.iter().any(|seen| seen.name == definition.name) {
+                visible.push(definition);
+            }
+        }
+        return visible;
+    }
+}
+
+/// A declaration shown in the document outline, with the methods of a class
+/// as its children.
+pub(super) struct OutlineItem {
+    pub(super) name: String,
+    pub(super) kind: DefinitionKind,
+    pub(super) span: Span,
+    pub(super) name_span: Span,
+    pub(super) children: Vec<OutlineItem>,
+}
+
+/// The top-level declarations of a program.
+pub(super) fn outline(tree: &SyntaxNode) -> Vec<OutlineItem> {
+    let mut items = Vec::new();
+    for node in tree.nodes() {
+        let kind = match node.kind() {
+            SyntaxKind::ClassDeclaration => DefinitionKind::Class,
+            SyntaxKind::FunctionDeclaration => DefinitionKind::Function,
+            SyntaxKind::VarDeclaration => DefinitionKind::Variable,
+            _ => continue,
+        };
+        let (Some(name), Some(span)) = (self::name(node), node.span()) else {
+            continue;
+        };
+        let mut children = Vec::new();
+        if kind == DefinitionKind::Class {
+            for method in node.nodes() {
+                let (Some(method_name), Some(method_span)) = (self::name(method), method.span())
+                else {
+                    continue;
+                };
+                children.push(OutlineItem {
+                    name: method_name.token().lexeme().to_string(),
+                    kind: DefinitionKind::Method,
+                    span: method_span,
+                    name_span: method_name.token().span(),
+                    children: Vec::new(),
+                });
+            }
+        }
+        items.push(OutlineItem {
+            name: name.token().lexeme().to_string(),
+            kind,
+            span,
+            name_span: name.token().span(),
+            children,
+        });
+    }
+    return items;
+}
+
+struct Walker {
+    definitions: Vec<Definition>,
+    references: Vec<(Span, usize)>,
+    /// The definitions of each enclosing scope, outermost first.
+    scopes: Vec<Vec<usize>>,
+    /// Names not declared in any enclosing local scope, resolved once every
+    /// global is known.
+    globals: Vec<GlobalUse>,
+    /// How many function bodies enclose the node being walked.
+    functions: usize,
+}
+
+struct GlobalUse {
+    span: Span,
+    name: String,
+    /// How many definitions came before the use, so a global's own
+    /// initializer doesn't see it.
+    declared: usize,
+    in_function: bool,
+}
+
+impl Walker {
+    fn node(&mut self, node: &SyntaxNode) {
+        match node.kind() {
+            SyntaxKind::Block | SyntaxKind::ForStatement => {
+                let scope = node.span().unwrap_or(everything());
+                self.scoped(scope, |walker| walker.children(node));
+            }
+            SyntaxKind::VarDeclaration => {
+                // The initializer can't see the variable it initializes.
+                self.children(node);
+                if let Some(name) = name(node) {
+                    let signature = format!("var {}", name.token().lexeme());
+                    self.define(name, DefinitionKind::Variable, signature, documented(node));
+                }
+            }
+            SyntaxKind::FunctionDeclaration => {
+                if let Some(name) = name(node) {
+                    let signature = format!("fun {}", function_signature(node));
+                    self.define(name, DefinitionKind::Function, signature, documented(node));
+                }
+                self.function(node);
+            }
+            SyntaxKind::ClassDeclaration => self.class(node),
+            SyntaxKind::Variable => {
+                if let Some(name) = name(node) {
+                    self.reference(name);
+                }
+            }
+            _ => self.children(node),
+        }
+    }
+
+    fn children(&mut self, node: &SyntaxNode) {
+        for child in node.nodes() {
+            self.node(child);
+        }
+    }
+
+    /// The parameters and body of a function or method, which share a
+    /// scope.
+    fn function(&mut self, node: &SyntaxNode) {
+        let parameters = node
+            .nodes()
+            .find(|child| child.kind() == SyntaxKind::Parameters);
+        let body = node.nodes().find(|child| child.kind() == SyntaxKind::Block);
+        let scope = match (parameters.and_then(SyntaxNode::span), node.span()) {
+            (Some(parameters), Some(function)) => Span::new(parameters.start, function.end),
+            _ => return,
+        };
+        self.functions += 1;
+        self.scoped(scope, |walker| {
+            for parameter in parameters.map(tokens).unwrap_or_default() {
+                if matches!(parameter.token().token_type(), TokenType::Identifier) {
+                    let signature = format!("(parameter) {}", parameter.token().lexeme());
+                    walker.define(parameter, DefinitionKind::Parameter, signature, None);
+                }
+            }
+            if let Some(body) = body {
+                walker.children(body);
+            }
+        });
+        self.functions -= 1;
+    }
+
+    fn class(&mut self, node: &SyntaxNode) {
+        let identifiers: Vec<&SyntaxToken> = tokens(node)
+            .into_iter()
+            .filter(|token| matches!(token.token().token_type(), TokenType::Identifier))
+            .collect();
+        if let Some(name) = identifiers.first() {
+            let mut signature = format!("class {}", name.token().lexeme());
+            if let Some(superclass) = identifiers.get(1) {
+                signature += &format!(" < {}", superclass.token().lexeme());
+                self.reference(superclass);
+            }
+            self.define(name, DefinitionKind::Class, signature, documented(node));
+        }
+        // Methods are looked up on instances at runtime, not by name.
+        for method in node.nodes() {
+            self.function(method);
+        }
+    }
+
+    fn scoped(&mut self, scope: Span, walk: impl FnOnce(&mut Walker)) {
+        self.scopes.push(Vec::new());
+        walk(self);
+        for index in self.scopes.pop().unwrap() {
+            self.definitions[index].scope = scope;
+        }
+    }
+
+    fn define(
+        &mut self,
+        name: &SyntaxToken,
+        kind: DefinitionKind,
+        signature: String,
+        documentation: Option<String>,
+    ) {
+        let index = self.definitions.len();
+        self.definitions.push(Definition {
+            name: name.token().lexeme().to_string(),
+            kind,
+            name_span: name.token().span(),
+            scope: everything(),
+            signature,
+            documentation,
+        });
+        self.scopes.last_mut().unwrap().push(index);
+    }
+
+    fn reference(&mut self, name: &SyntaxToken) {
+        let text = name.token().lexeme();
+        // The outermost scope holds globals, which are bound late.
+        for scope in self.scopes[1..].iter().rev() {
+            if let Some(&index) = scope
+                .iter()
+                .rev()
+                .find(|&&index| self.definitions[index].name == text)
+            {
+                self.references.push((name.token().span(), index));
+                return;
+            }
+        }
+        self.globals.push(GlobalUse {
+            span: name.token().span(),
+            name: text.to_string(),
+            declared: self.definitions.len(),
+            in_function: self.functions > 0,
+        });
+    }
+
+    /// Binds each remaining name to the latest global declared before it,
+    /// or, inside a function called later, the first one declared at all.
+    /// Outside functions a name used before any declaration stays unbound,
+    /// as it fails at runtime.
+    fn resolve_globals(&mut self) {
+        let globals: Vec<usize> = (0..self.definitions.len())
+            .filter(|&index| self.definitions[index].scope == everything())
+            .collect();
+        for global in std::mem::take(&mut self.globals) {
+            let named: Vec<usize> = globals
+                .iter()
+                .copied()
+                .filter(|&index| self.definitions[index].name == global.name)
+                .collect();
+            let before = named.iter().rev().find(|&&index| index < global.declared);
+            let later = named.first().filter(|_| global.in_function);
+            if let Some(&index) = before.or(later) {
+                self.references.push((global.span, index));
+            }
+        }
+        self.references.sort_by_key(|(span, _)| span.start);
+    }
+}
+
+/// The scope of a global.
+fn everything() -> Span {
+    return Span::new(0, usize::MAX);
+}
+
+/// The name a declaration or variable node introduces or uses: its first
+/// identifier token.
+fn name(node: &SyntaxNode) -> Option<&SyntaxToken> {
+    return node.children().iter().find_map(|child| match child {
+        SyntaxElement::Token(token)
+            if matches!(token.token().token_type(), TokenType::Identifier) =>
+        {
+            Some(token)
+        }
+        _ => None,
+    });
+}
+
+/// The tokens directly under `node`, not those of its child nodes.
+fn tokens(node: &SyntaxNode) -> Vec<&SyntaxToken> {
+    return node
+        .children()
+        .iter()
+        .filter_map(|child| match child {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(_) => None,
+        })
+        .collect();
+}
+
+/// `name(a, b)` for a function declaration.
+fn function_signature(node: &SyntaxNode) -> String {
+    let parameters: Vec<&str> = node
+        .nodes()
+        .find(|child| child.kind() == SyntaxKind::Parameters)
+        .map(tokens)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|token| matches!(token.token().token_type(), TokenType::Identifier))
+        .map(|token| token.token().lexeme())
+        .collect();
+    let name = name(node).map_or("", |name| name.token().lexeme());
+    return format!("{}({})", name, parameters.join(", "));
+}
+
+/// The comment lines right above a declaration, without their `//`. A
+/// blank line ends them.
+fn documented(declaration: &SyntaxNode) -> Option<String> {
+    let tokens = declaration.tokens();
+    let token = tokens.first()?;
+    let mut lines = Vec::new();
+    let mut newlines = 0;
+    for trivia in token.leading().iter().rev() {
+        match trivia.kind() {
+            TriviaKind::Whitespace => {}
+            TriviaKind::Newline => {
+                newlines += 1;
+                if newlines > 1 {
+                    break;
+                }
+            }
+            TriviaKind::Comment => {
+                let text = &trivia.text()[2..];
+                lines.push(text.strip_prefix(' ').unwrap_or(text));
+                newlines = 0;
+            }
+            TriviaKind::Skipped => break,
+        }
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    lines.reverse();
+    return Some(lines.join("\n"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cst;
+
+    /// The offset of the `nth` occurrence of the word `word` in `source`,
+    /// counting from zero.
+    fn at(source: &str, word: &str, nth: usize) -> usize {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        return source
+            .match_indices(word)
+            .map(|(offset, _)| offset)
+            .filter(|&offset| {
+                let before = source[..offset].chars().next_back();
+                let after = source[offset + word.len()..].chars().next();
+                return !before.is_some_and(is_word) && !after.is_some_and(is_word);
+            })
+            .nth(nth)
+            .unwrap();
+    }
+
+    /// Where the declaration of the name at `offset` is written, if it
+    /// resolves to one.
+    fn declaration(source: &str, offset: usize) -> Option<usize> {
+        let analysis = Analysis::new(&cst::parse(source));
+        let (index, _) = analysis.definition_at(offset)?;
+        return Some(analysis.definitions[index].name_span.start);
+    }
+
+    #[test]
+    fn locals_shadow_outer_names() {
+        let source = "var a = 1;\n{\n  var a = 2;\n  print a;\n}\nprint a;\n";
+        assert_eq!(
+            declaration(source, at(source, "a", 2)),
+            Some(at(source, "a", 1))
+        );
+        assert_eq!(
+            declaration(source, at(source, "a", 3)),
+            Some(at(source, "a", 0))
+        );
+    }
+
+    #[test]
+    fn initializers_dont_see_their_own_variable() {
+        let source = "var a = 1;\n{\n  var a = a;\n}\n";
+        assert_eq!(
+            declaration(source, at(source, "a", 2)),
+            Some(at(source, "a", 0))
+        );
+
+        let source = "var a = 1;\nvar a = a;\n";
+        assert_eq!(
+            declaration(source, at(source, "a", 2)),
+            Some(at(source, "a", 0))
+        );
+
+        let source = "var a = a;\n";
+        assert_eq!(declaration(source, at(source, "a", 1)), None);
+    }
+
+    #[test]
+    fn functions_see_globals_declared_after_them() {
+        let source =
+            "fun f() {\n  return g() + later;\n}\nfun g() {\n  return f;\n}\nvar later = 1;\n";
+        assert_eq!(
+            declaration(source, at(source, "g", 0)),
+            Some(at(source, "g", 1))
+        );
+        assert_eq!(
+            declaration(source, at(source, "later", 0)),
+            Some(at(source, "later", 1))
+        );
+        assert_eq!(
+            declaration(source, at(source, "f", 1)),
+            Some(at(source, "f", 0))
+        );
+
+        let source = "print later;\nvar later = 1;\n";
+        assert_eq!(declaration(source, at(source, "later", 0)), None);
+    }
+
+    #[test]
+    fn parameters_are_local_to_their_function() {
+        let source = "var a = 0;\nfun add(a, b) {\n  return a + b;\n}\nprint a;\n";
+        assert_eq!(
+            declaration(source, at(source, "a", 2)),
+            Some(at(source, "a", 1))
+        );
+        assert_eq!(
+            declaration(source, at(source, "b", 1)),
+            Some(at(source, "b", 0))
+        );
+        assert_eq!(
+            declaration(source, at(source, "a", 3)),
+            Some(at(source, "a", 0))
+        );
+    }
+
+    #[test]
+    fn references_are_every_use_in_order() {
+        let source =
+            "var n = 1;\nfun f() {\n  return n;\n}\n{\n  var n = 2;\n  print n;\n}\nn = n + 1;\n";
+        let analysis = Analysis::new(&cst::parse(source));
+        let (global, _) = analysis.definition_at(at(source, "n", 0)).unwrap();
+        let uses: Vec<usize> = analysis
+            .references_to(global)
+            .iter()
+            .map(|span| span.start)
+            .collect();
+        assert_eq!(
+            uses,
+            [at(source, "n", 1), at(source, "n", 4), at(source, "n", 5)]
+        );
+
+        // A cursor just after a name still finds it.
+        let end = at(source, "n", 3) + 1;
+        let (local, _) = analysis.definition_at(end).unwrap();
+        assert_eq!(analysis.references_to(local).len(), 1);
+    }
+
+    #[test]
+    fn visible_names_are_locals_declared_so_far_and_every_global() {
+        let source =
+            "var a = 1;\n{\n  var b = 2;\n  var a = 3;\n  print a;\n  var c = 4;\n}\nfun later() {}\n";
+        let analysis = Analysis::new(&cst::parse(source));
+        let visible = analysis.visible_at(at(source, "print", 0));
+        let mut names: Vec<(&str, usize)> = visible
+            .iter()
+            .map(|definition| (definition.name.as_str(), definition.name_span.start))
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                ("a", at(source, "a", 1)),
+                ("b", at(source, "b", 0)),
+                ("later", at(source, "later", 0)),
+            ]
+        );
+
+        let outside = analysis.visible_at(source.len());
+        assert!(outside.iter().all(|definition| definition.name != "b"));
+    }
+}
@@ -1,776 +1,263 @@
+#![allow(clippy::needless_return)]
+
 use std::env;
-use std::fmt;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
 use std::process::exit;
 
-#[derive(Clone)]
-pub enum TokenType {
-    LeftParen,
-    RightParen,
-    LeftBrace,
-    RightBrace,
-
-    Comma,
-    Dot,
-    Minus,
-    Plus,
-    Semicolon,
-    Slash,
-    Star,
-
-    Equal,
-    EqualEqual,
-    Bang,
-    BangEqual,
-    Less,
-    LessEqual,
-    Greater,
-    GreaterEqual,
-
-    Identifier,
-
-    StringLiteral(String),
-    Number(f64),
-
-    And,
-    Class,
-    Else,
-    False,
-    Fun,
-    For,
-    If,
-    Nil,
-    Or,
-    Print,
-    Return,
-    Super,
-    This,
-    True,
-    Var,
-    While,
-
-    Eof,
-}
+use lox::{
+    cst, debugger, diagnostic, formatter, json, lsp, optimizer, Interpreter, LoxError, ParseError,
+    Scanner, Vm,
+};
 
-impl fmt::Display for TokenType {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            TokenType::LeftParen => write!(f, "LEFT_PAREN"),
-            TokenType::RightParen => write!(f, "RIGHT_PAREN"),
-            TokenType::LeftBrace => write!(f, "LEFT_BRACE"),
-            TokenType::RightBrace => write!(f, "RIGHT_BRACE"),
+fn tokenize(source: &str, options: &Options) -> Result<(), LoxError> {
+    let (tokens, errors) = Scanner::scan(source.to_string());
 
-            TokenType::Comma => write!(f, "COMMA"),
-            TokenType::Dot => write!(f, "DOT"),
-            TokenType::Minus => write!(f, "MINUS"),
-            TokenType::Plus => write!(f, "PLUS"),
-            TokenType::Semicolon => write!(f, "SEMICOLON"),
-            TokenType::Slash => write!(f, "SLASH"),
-            TokenType::Star => write!(f, "STAR"),
-
-            TokenType::Equal => write!(f, "EQUAL"),
-            TokenType::EqualEqual => write!(f, "EQUAL_EQUAL"),
-            TokenType::Bang => write!(f, "BANG"),
-            TokenType::BangEqual => write!(f, "BANG_EQUAL"),
-            TokenType::Less => write!(f, "LESS"),
-            TokenType::LessEqual => write!(f, "LESS_EQUAL"),
-            TokenType::Greater => write!(f, "GREATER"),
-            TokenType::GreaterEqual => write!(f, "GREATER_EQUAL"),
-
-            TokenType::Identifier => write!(f, "IDENTIFIER"),
-
-            TokenType::StringLiteral(_) => write!(f, "STRING"),
-            TokenType::Number(_) => write!(f, "NUMBER"),
-
-            TokenType::And => write!(f, "AND"),
-            TokenType::Class => write!(f, "CLASS"),
-            TokenType::Else => write!(f, "ELSE"),
-            TokenType::False => write!(f, "FALSE"),
-            TokenType::Fun => write!(f, "FUN"),
-            TokenType::For => write!(f, "FOR"),
-            TokenType::If => write!(f, "IF"),
-            TokenType::Nil => write!(f, "NIL"),
-            TokenType::Or => write!(f, "OR"),
-            TokenType::Print => write!(f, "PRINT"),
-            TokenType::Return => write!(f, "RETURN"),
-            TokenType::Super => write!(f, "SUPER"),
-            TokenType::This => write!(f, "THIS"),
-            TokenType::True => write!(f, "TRUE"),
-            TokenType::Var => write!(f, "VAR"),
-            TokenType::While => write!(f, "WHILE"),
-            TokenType::Eof => write!(f, "EOF"),
+    match options.format {
+        Format::Text => {
+            for token in tokens {
+                println!("{}", token);
+            }
         }
+        Format::Json => println!("{}", json::tokens(&tokens)),
     }
-}
-
-#[derive(Clone)]
-pub struct Token {
-    _type: TokenType,
-    lexeme: String,
-    line: usize,
-}
 
-impl fmt::Display for Token {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let literal = match &self._type {
-            TokenType::StringLiteral(value) => value.to_string(),
-            TokenType::Number(value) => {
-                let integer: f64 = (*value as i64) as f64;
-                if integer.to_bits() == value.to_bits() {
-                    format!("{}.0", integer)
-                } else {
-                    value.to_string()
-                }
-            }
-            _ => "null".to_string(),
-        };
-        write!(f, "{} {} {}", self._type, self.lexeme, literal)
+    if !errors.is_empty() {
+        return Err(LoxError::Compile(
+            errors.into_iter().map(ParseError::from).collect(),
+        ));
     }
+    return Ok(());
 }
 
-fn check_reserved(word: &String) -> TokenType {
-    match word.as_str() {
-        "and" => TokenType::And,
-        "class" => TokenType::Class,
-        "else" => TokenType::Else,
-        "false" => TokenType::False,
-
-        "for" => TokenType::For,
-        "fun" => TokenType::Fun,
-        "if" => TokenType::If,
-        "nil" => TokenType::Nil,
-
-        "or" => TokenType::Or,
-        "print" => TokenType::Print,
-        "return" => TokenType::Return,
-        "super" => TokenType::Super,
-
-        "this" => TokenType::This,
-        "true" => TokenType::True,
-        "var" => TokenType::Var,
-        "while" => TokenType::While,
-
-        _ => TokenType::Identifier,
+fn parse(source: &str, options: &Options) -> Result<(), LoxError> {
+    let expression = lox::parse_expression(source)?;
+    match options.format {
+        Format::Text => println!("{}", expression),
+        Format::Json => println!("{}", json::expression(&expression)),
     }
+    return Ok(());
 }
 
-pub struct Scanner {}
-
-impl Scanner {
-    pub fn scan(file_contents: String) -> (Vec<Token>, i32) {
-        let mut exit_code = 0;
-        let mut tokens: Vec<Token> = Vec::<Token>::new();
-        let mut line_number = 1;
-        if !file_contents.is_empty() {
-            let mut file_content_chars = file_contents.chars().peekable();
-
-            while let Some(char) = file_content_chars.next() {
-                match char {
-                    '(' => tokens.push(Token {
-                        _type: TokenType::LeftParen,
-                        lexeme: "(".to_string(),
-                        line: line_number,
-                    }),
-                    ')' => tokens.push(Token {
-                        _type: TokenType::RightParen,
-                        lexeme: ")".to_string(),
-                        line: line_number,
-                    }),
-                    '{' => tokens.push(Token {
-                        _type: TokenType::LeftBrace,
-                        lexeme: "{".to_string(),
-                        line: line_number,
-                    }),
-                    '}' => tokens.push(Token {
-                        _type: TokenType::RightBrace,
-                        lexeme: "}".to_string(),
-                        line: line_number,
-                    }),
-                    ',' => tokens.push(Token {
-                        _type: TokenType::Comma,
-                        lexeme: ",".to_string(),
-                        line: line_number,
-                    }),
-                    '.' => tokens.push(Token {
-                        _type: TokenType::Dot,
-                        lexeme: ".".to_string(),
-                        line: line_number,
-                    }),
-                    '-' => tokens.push(Token {
-                        _type: TokenType::Minus,
-                        lexeme: "-".to_string(),
-                        line: line_number,
-                    }),
-                    '+' => tokens.push(Token {
-                        _type: TokenType::Plus,
-                        lexeme: "+".to_string(),
-                        line: line_number,
-                    }),
-                    ';' => tokens.push(Token {
-                        _type: TokenType::Semicolon,
-                        lexeme: ";".to_string(),
-                        line: line_number,
-                    }),
-                    '*' => tokens.push(Token {
-                        _type: TokenType::Star,
-                        lexeme: "*".to_string(),
-                        line: line_number,
-                    }),
-                    '=' => match file_content_chars.peek() {
-                        Some('=') => {
-                            file_content_chars.next();
-                            tokens.push(Token {
-                                _type: TokenType::EqualEqual,
-                                lexeme: "==".to_string(),
-                                line: line_number,
-                            });
-                        }
-                        _ => tokens.push(Token {
-                            _type: TokenType::Equal,
-                            lexeme: "=".to_string(),
-                            line: line_number,
-                        }),
-                    },
-                    '!' => match file_content_chars.peek() {
-                        Some('=') => {
-                            file_content_chars.next();
-                            tokens.push(Token {
-                                _type: TokenType::BangEqual,
-                                lexeme: "!=".to_string(),
-                                line: line_number,
-                            });
-                        }
-                        _ => tokens.push(Token {
-                            _type: TokenType::Bang,
-                            lexeme: "!".to_string(),
-                            line: line_number,
-                        }),
-                    },
-                    '<' => match file_content_chars.peek() {
-                        Some('=') => {
-                            file_content_chars.next();
-                            tokens.push(Token {
-                                _type: TokenType::LessEqual,
-                                lexeme: "<=".to_string(),
-                                line: line_number,
-                            });
-                        }
-                        _ => tokens.push(Token {
-                            _type: TokenType::Less,
-                            lexeme: "<".to_string(),
-                            line: line_number,
-                        }),
-                    },
-                    '>' => match file_content_chars.peek() {
-                        Some('=') => {
-                            file_content_chars.next();
-                            tokens.push(Token {
-                                _type: TokenType::GreaterEqual,
-                                lexeme: ">=".to_string(),
-                                line: line_number,
-                            });
-                        }
-                        _ => tokens.push(Token {
-                            _type: TokenType::Greater,
-                            lexeme: ">".to_string(),
-                            line: line_number,
-                        }),
-                    },
-                    '/' => match file_content_chars.peek() {
-                        Some('/') => {
-                            let mut next_char = file_content_chars.next();
-                            while next_char != None && next_char != Some('\n') {
-                                next_char = file_content_chars.next();
-                            }
-                            line_number += 1;
-                        }
-                        _ => tokens.push(Token {
-                            _type: TokenType::Slash,
-                            lexeme: "/".to_string(),
-                            line: line_number,
-                        }),
-                    },
-                    '"' => {
-                        let mut string = String::new();
-                        let mut next_char = file_content_chars.next();
-                        while next_char != None && next_char != Some('"') {
-                            string.push(next_char.unwrap());
-                            next_char = file_content_chars.next();
-                        }
-                        let mut is_closed = false;
-                        match next_char {
-                            None => {}
-                            Some(_) => is_closed = true,
-                        }
-                        if is_closed == true {
-                            let mut quoted: String = "\"".to_string();
-                            quoted.push_str(&string);
-                            quoted.push('"');
-                            tokens.push(Token {
-                                _type: TokenType::StringLiteral(string.clone()),
-                                lexeme: quoted,
-                                line: line_number,
-                            });
-                        } else {
-                            eprintln!("[line {}] Error: Unterminated string.", line_number);
-                            exit_code = 65;
-                        }
-                    }
-                    char if char.is_numeric() == true => {
-                        let mut string = String::new();
-                        string.push(char);
-                        while let Some(next_char) = file_content_chars.peek() {
-                            if next_char.is_numeric() || *next_char == '.' {
-                                string.push(*next_char);
-                                file_content_chars.next();
-                            } else {
-                                break;
-                            }
-                        }
-                        tokens.push(Token {
-                            _type: TokenType::Number(string.parse().unwrap()),
-                            lexeme: string,
-                            line: line_number,
-                        });
-                    }
-                    char if char.is_alphabetic() || char == '_' => {
-                        let mut string = String::from(char);
-                        while let Some(next_char) = file_content_chars.peek() {
-                            if next_char.is_alphanumeric() || *next_char == '_' {
-                                string.push(*next_char);
-                                file_content_chars.next();
-                            } else {
-                                break;
-                            }
-                        }
-                        let token_type = check_reserved(&string);
-                        tokens.push(Token {
-                            _type: token_type,
-                            lexeme: string,
-                            line: line_number,
-                        });
-                    }
-                    '\n' => line_number += 1,
-                    '\t' | ' ' => {}
-                    _ => {
-                        eprintln!(
-                            "[line {}] Error: Unexpected character: {}",
-                            line_number, char
-                        );
-                        exit_code = 65;
-                    }
-                }
-            }
-        }
-
-        tokens.push(Token {
-            _type: TokenType::Eof,
-            lexeme: "".to_string(),
-            line: line_number,
-        });
+/// Prints the lossless syntax tree, which parses anything, so this never
+/// reports errors.
+fn syntax_tree(source: &str) -> Result<(), LoxError> {
+    print!("{}", cst::parse(source).dump());
+    return Ok(());
+}
 
-        return (tokens, exit_code);
+fn evaluate(source: &str, options: &Options) -> Result<(), LoxError> {
+    let mut expression = lox::parse_expression(source)?;
+    if options.optimize {
+        expression = optimizer::optimize_expression(expression);
     }
+    let value = Interpreter::new().evaluate(&expression)?;
+    println!("{}", value);
+    return Ok(());
 }
 
-enum Expression {
-    Literal(Token),
-    Unary(Token, Box<Expression>),
-    Binary(Box<Expression>, Token, Box<Expression>),
-    Grouping(Box<Expression>),
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Backend {
+    #[default]
+    Tree,
+    Vm,
 }
 
-impl fmt::Display for Expression {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Expression::Literal(value) => {
-                let literal = match &value._type {
-                    TokenType::StringLiteral(value) => value.to_string(),
-                    TokenType::Number(value) => {
-                        let integer: f64 = (*value as i64) as f64;
-                        if integer.to_bits() == value.to_bits() {
-                            format!("{}.0", integer)
-                        } else {
-                            value.to_string()
-                        }
-                    }
-                    _ => format!("{}", value.lexeme),
-                };
-                write!(f, "{}", literal)
-            }
-            Expression::Unary(operator, expression) => {
-                write!(f, "({} {})", operator.lexeme, expression)
-            }
-            Expression::Binary(left, operator, right) => {
-                write!(f, "({} {} {})", operator.lexeme, left, right)
-            }
-            Expression::Grouping(expression) => write!(f, "(group {})", expression),
-        }
-    }
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Format {
+    #[default]
+    Text,
+    Json,
 }
 
-pub struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
+struct Options {
+    backend: Backend,
+    format: Format,
+    stress_gc: bool,
+    optimize: bool,
+    check: bool,
 }
 
-type ParserResult = Result<Expression, String>;
-
-impl Parser {
-    fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
-    }
-
-    fn parse(&mut self) -> ParserResult {
-        return self.expression();
-    }
-
-    fn expression(&mut self) -> ParserResult {
-        return self.equality();
-    }
-
-    fn equality(&mut self) -> ParserResult {
-        let mut lhs = self.comparison()?;
-
-        while matches!(
-            self.peek()._type,
-            TokenType::BangEqual | TokenType::EqualEqual
-        ) {
-            let operator = self.advance();
-            let rhs = self.comparison()?;
-            lhs = Expression::Binary(Box::new(lhs), operator, Box::new(rhs));
-        }
-
-        return Ok(lhs);
-    }
-
-    fn comparison(&mut self) -> ParserResult {
-        let mut lhs = self.term()?;
-
-        while matches!(
-            self.peek()._type,
-            TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual
-        ) {
-            let operator = self.advance();
-            let rhs = self.term()?;
-            lhs = Expression::Binary(Box::new(lhs), operator, Box::new(rhs));
-        }
-
-        return Ok(lhs);
-    }
-
-    fn term(&mut self) -> ParserResult {
-        let mut lhs = self.factor()?;
-
-        while matches!(self.peek()._type, TokenType::Minus | TokenType::Plus) {
-            let operator = self.advance();
-            let rhs = self.factor()?;
-            lhs = Expression::Binary(Box::new(lhs), operator, Box::new(rhs));
-        }
-
-        return Ok(lhs);
-    }
-
-    fn factor(&mut self) -> ParserResult {
-        let mut left = self.unary()?;
-
-        while matches!(self.peek()._type, TokenType::Slash | TokenType::Star) {
-            let operator = self.advance();
-            let right = self.unary()?;
-            left = Expression::Binary(Box::new(left), operator, Box::new(right));
-        }
-
-        return Ok(left);
-    }
-
-    fn unary(&mut self) -> ParserResult {
-        if matches!(self.peek()._type, TokenType::Bang | TokenType::Minus) {
-            let operator = self.advance();
-            let right = self.unary()?;
-
-            return Ok(Expression::Unary(operator, Box::new(right)));
-        }
-
-        return self.primary();
-    }
-
-    fn primary(&mut self) -> ParserResult {
-        if matches!(
-            self.peek()._type,
-            TokenType::False
-                | TokenType::True
-                | TokenType::Nil
-                | TokenType::Number(_)
-                | TokenType::StringLiteral(_)
-        ) {
-            return Ok(Expression::Literal(self.advance()));
-        }
-
-        if matches!(self.peek()._type, TokenType::LeftParen) {
-            self.advance();
-            let expression = self.expression()?;
-
-            if !matches!(self.peek()._type, TokenType::RightParen) {
-                return Err(Parser::error(
-                    self.peek().clone(),
-                    "Expect ')' after expression".to_string(),
-                ));
+impl Options {
+    fn parse(options: &[String]) -> Result<Options, String> {
+        let mut parsed = Options {
+            backend: Backend::default(),
+            format: Format::default(),
+            stress_gc: false,
+            optimize: true,
+            check: false,
+        };
+        for option in options {
+            match option.as_str() {
+                "--backend=tree" => parsed.backend = Backend::Tree,
+                "--backend=vm" => parsed.backend = Backend::Vm,
+                "--format=text" => parsed.format = Format::Text,
+                "--format=json" => parsed.format = Format::Json,
+                "--stress-gc" => parsed.stress_gc = true,
+                "--no-opt" => parsed.optimize = false,
+                "--check" => parsed.check = true,
+                _ => return Err(format!("Unknown option: {}", option)),
             }
-            self.advance();
-            return Ok(Expression::Grouping(Box::new(expression)));
         }
-        return Err(Parser::error(
-            self.peek().clone(),
-            "Unknown Error".to_string(),
-        ));
+        return Ok(parsed);
     }
+}
 
-    fn error(token: Token, message: String) -> String {
-        match token._type {
-            TokenType::Eof => token.line.to_string() + " at end. " + &message,
-            _ => token.line.to_string() + " at '" + &token.lexeme + "' " + &message,
+fn run(source: &str, options: &Options) -> Result<(), LoxError> {
+    match options.backend {
+        Backend::Vm => {
+            let mut vm = Vm::new();
+            vm.set_stress_gc(options.stress_gc);
+            vm.set_optimize(options.optimize);
+            return vm.run(source);
         }
-    }
-
-    fn advance(&mut self) -> Token {
-        if !self.is_at_end() {
-            self.current += 1;
+        Backend::Tree => {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_optimize(options.optimize);
+            return interpreter.run(source);
         }
-        return self.previous();
     }
+}
 
-    fn is_at_end(&self) -> bool {
-        return matches!(self.peek()._type, TokenType::Eof);
+/// Rewrites the file in place, or with `--check` only reports whether it
+/// would change.
+fn format(filename: &str, source: &str, options: &Options) -> Result<(), LoxError> {
+    let formatted = formatter::format(source)?;
+    if formatted == source {
+        return Ok(());
     }
-
-    fn peek(&self) -> &Token {
-        return &self.tokens[self.current];
+    if options.check {
+        eprintln!("{} is not formatted", filename);
+        exit(1);
     }
-
-    fn previous(&self) -> Token {
-        return self.tokens[self.current - 1].clone();
+    if let Err(error) = fs::write(filename, formatted) {
+        eprintln!("Failed to write file {}: {}", filename, error);
+        exit(1);
     }
+    return Ok(());
 }
 
-pub struct Interpreter {}
-
-#[derive(PartialEq)]
-enum Object {
-    Nil,
-    Boolean(bool),
-    Number(f64),
-    String(String),
+/// Runs the file under the terminal debugger, which starts paused.
+fn debug(source: &str, options: &Options) -> Result<(), LoxError> {
+    return debugger::console::run(source, options.optimize);
 }
 
-impl fmt::Display for Object {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Object::Nil => writeln!(f, "nil"),
-            Object::Boolean(b) => writeln!(f, "{}", b),
-            Object::Number(n) => writeln!(f, "{}", n),
-            Object::String(s) => writeln!(f, "{}", s),
-        }
-    }
+fn disassemble(source: &str, options: &Options) -> Result<(), LoxError> {
+    print!("{}", lox::vm::disassemble(source, options.optimize)?);
+    return Ok(());
 }
 
-type InterpreterResult = Result<Object, String>;
+fn repl() {
+    let mut interpreter = Interpreter::new();
+    let mut source = String::new();
 
-impl Interpreter {
-    fn evaluate(&self, expression: &Expression) -> InterpreterResult {
-        match expression {
-            Expression::Literal(value) => match &value._type {
-                TokenType::Nil => return Ok(Object::Nil),
-                TokenType::True => return Ok(Object::Boolean(true)),
-                TokenType::False => return Ok(Object::Boolean(false)),
-                TokenType::Number(n) => return Ok(Object::Number(*n)),
-                TokenType::StringLiteral(s) => return Ok(Object::String(s.to_string())),
-                _ => return Err(format!("Wrong literal")),
-            },
-            Expression::Unary(operator, value) => {
-                let right = self.evaluate(value)?;
-                match operator._type {
-                    TokenType::Minus => match right {
-                        Object::Number(n) => return Ok(Object::Number(-n)),
-                        _ => Err(format!("Operand must be a number")),
-                    },
-                    TokenType::Bang => return Ok(Object::Boolean(!Interpreter::is_truthy(&right))),
-                    _ => Err(format!("Wrong unari operator")),
-                }
-            }
-            Expression::Binary(lhs, operator, rhs) => {
-                let lhs = self.evaluate(lhs)?;
-                let rhs = self.evaluate(rhs)?;
+    loop {
+        print!("{}", if source.is_empty() { "> " } else { "... " });
+        io::stdout().flush().unwrap();
 
-                match operator._type {
-                    TokenType::Minus => match (lhs, rhs) {
-                        (Object::Number(n), Object::Number(m)) => return Ok(Object::Number(n - m)),
-                        _ => return Err(format!("Operands must be numbers")),
-                    },
-                    TokenType::Slash => match (lhs, rhs) {
-                        (Object::Number(n), Object::Number(m)) => return Ok(Object::Number(n / m)),
-                        _ => return Err(format!("Operands must be numbers")),
-                    },
-                    TokenType::Star => match (lhs, rhs) {
-                        (Object::Number(n), Object::Number(m)) => return Ok(Object::Number(n * m)),
-                        _ => return Err(format!("Operands must be numbers")),
-                    },
-                    TokenType::Plus => match (lhs, rhs) {
-                        (Object::Number(n), Object::Number(m)) => return Ok(Object::Number(n + m)),
-                        (Object::String(s), Object::String(t)) => {
-                            return Ok(Object::String(s + &t))
-                        }
-                        _ => return Err(format!("Operands must be numbers or strings")),
-                    },
-                    TokenType::Greater => match (lhs, rhs) {
-                        (Object::Number(n), Object::Number(m)) => {
-                            return Ok(Object::Boolean(n > m))
-                        }
-                        _ => return Err(format!("Operands must be numbers")),
-                    },
-                    TokenType::GreaterEqual => match (lhs, rhs) {
-                        (Object::Number(n), Object::Number(m)) => {
-                            return Ok(Object::Boolean(n >= m))
-                        }
-                        _ => {
-                            let err = format!("Operands must be numbers");
-                            eprintln!("{}", err);
-                            return Err(err);
-                        }
-                    },
-                    TokenType::Less => match (lhs, rhs) {
-                        (Object::Number(n), Object::Number(m)) => {
-                            return Ok(Object::Boolean(n < m))
-                        }
-                        _ => {
-                            let err = format!("Operands must be numbers");
-                            eprintln!("{}", err);
-                            return Err(err);
-                        }
-                    },
-                    TokenType::LessEqual => match (lhs, rhs) {
-                        (Object::Number(n), Object::Number(m)) => {
-                            return Ok(Object::Boolean(n <= m))
-                        }
-                        _ => {
-                            let err = format!("Operands must be numbers");
-                            eprintln!("{}", err);
-                            return Err(err);
-                        }
-                    },
-                    TokenType::BangEqual => {
-                        return Ok(Object::Boolean(!Interpreter::is_equal(&lhs, &rhs)))
-                    }
-                    TokenType::EqualEqual => {
-                        return Ok(Object::Boolean(Interpreter::is_equal(&lhs, &rhs)))
-                    }
-                    _ => return Err(format!("Wrong binary expression")),
-                }
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                println!();
+                return;
             }
-            Expression::Grouping(value) => return self.evaluate(value),
+            Ok(_) => source.push_str(&line),
         }
-    }
 
-    fn is_truthy(object: &Object) -> bool {
-        match object {
-            Object::Nil => false,
-            Object::Boolean(b) => *b,
-            _ => true,
+        if lox::is_incomplete(&source) {
+            continue;
         }
-    }
 
-    fn is_equal(lhs: &Object, rhs: &Object) -> bool {
-        match (lhs, rhs) {
-            (Object::Nil, Object::Nil) => return true,
-            (Object::Nil, _) => return false,
-            (a, b) => return a == b,
+        let input = mem::take(&mut source);
+        match interpreter.run_line(&input) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) => {}
+            Err(error) => eprint!("{}", diagnostic::render(&input, &error)),
         }
     }
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        writeln!(io::stderr(), "Usage: {} tokenize <filename>", args[0]).unwrap();
+    let (options, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    if args.len() == 1 || args[1] == "repl" {
+        repl();
         return;
     }
-
-    let command = &args[1];
-    let filename = &args[2];
-
-    match command.as_str() {
-        "tokenize" => {
-            let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-                String::new()
-            });
-
-            let (tokens, exit_code) = Scanner::scan(file_contents);
-
-            for token in tokens {
-                println!("{}", token);
-            }
-
-            if exit_code != 0 {
-                exit(exit_code)
+    if args[1] == "lsp" {
+        match lsp::serve(io::stdin().lock(), io::stdout().lock()) {
+            Ok(code) => exit(code),
+            Err(error) => {
+                eprintln!("{}", error);
+                exit(1);
             }
         }
-        "parse" => {
-            let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-                String::new()
-            });
-
-            let (tokens, exit_code) = Scanner::scan(file_contents);
-
-            if exit_code != 0 {
-                exit(exit_code)
-            }
-
-            let expression = Parser::new(tokens).parse();
-            match expression {
-                Ok(n) => println!("{}", n),
-                Err(e) => {
-                    eprintln!("{}", e);
-                    exit(65);
-                }
+    }
+    if args[1] == "dap" {
+        match debugger::dap::serve(BufReader::new(io::stdin()), io::stdout()) {
+            Ok(code) => exit(code),
+            Err(error) => {
+                eprintln!("{}", error);
+                exit(1);
             }
         }
-        "evaluate" => {
-            let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-                String::new()
-            });
-
-            let (tokens, exit_code) = Scanner::scan(file_contents);
-
-            if exit_code != 0 {
-                exit(exit_code)
-            }
+    }
+    if args.len() < 3 {
+        eprintln!(
+            "Usage: {} [tokenize|parse|cst|evaluate|run|debug|disassemble|fmt] <filename>",
+            args[0]
+        );
+        eprintln!(
+            "       {} [tokenize|parse] [--format=text|--format=json] <filename>",
+            args[0]
+        );
+        eprintln!(
+            "       {} run [--backend=tree|--backend=vm] [--stress-gc] [--no-opt] <filename>",
+            args[0]
+        );
+        eprintln!("       {} debug [--no-opt] <filename>", args[0]);
+        eprintln!("       {} fmt [--check] <filename>", args[0]);
+        eprintln!("       {} [repl|lsp|dap]", args[0]);
+        return;
+    }
 
-            let expression = Parser::new(tokens).parse();
-            match &expression {
-                Ok(n) => (),
-                Err(e) => {
-                    eprintln!("{}", e);
-                    exit(65);
-                }
-            }
+    let command = &args[1];
+    let filename = &args[2];
 
-            let expression = expression.unwrap();
-            let interpreter = Interpreter {};
-            let value = interpreter.evaluate(&expression);
-            match &value {
-                Ok(n) => println!("{}", n),
-                Err(e) => {
-                    eprintln!("{}", e);
-                    exit(70);
-                }
-            }
+    let options = match Options::parse(&options) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            return;
         }
+    };
+
+    let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
+        eprintln!("Failed to read file {}", filename);
+        String::new()
+    });
+
+    let result = match command.as_str() {
+        "tokenize" => tokenize(&file_contents, &options),
+        "parse" => parse(&file_contents, &options),
+        "cst" => syntax_tree(&file_contents),
+        "evaluate" => evaluate(&file_contents, &options),
+        "run" => run(&file_contents, &options),
+        "debug" => debug(&file_contents, &options),
+        "disassemble" => disassemble(&file_contents, &options),
+        "fmt" => format(filename, &file_contents, &options),
         _ => {
-            writeln!(io::stderr(), "Unknown command: {}", command).unwrap();
+            eprintln!("Unknown command: {}", command);
             return;
         }
+    };
+
+    if let Err(error) = result {
+        match options.format {
+            Format::Text => eprint!("{}", diagnostic::render(&file_contents, &error)),
+            Format::Json => eprintln!("{}", json::diagnostics(&error)),
+        }
+        exit(error.exit_code());
     }
 }
@@ -0,0 +1,293 @@
+use std::rc::Rc;
+
+use crate::ast::{Expression, FunctionDeclaration, Statement};
+use crate::intern::Symbol;
+use crate::scanner::{Token, TokenType};
+
+/// Folds constant expressions and drops branches that can never run.
+///
+/// Only operations whose result doesn't depend on runtime state are folded,
+/// and only when they can't fail: `-"a"` or `1 + nil` are left alone so they
+/// still raise their error when executed. Runs after resolution, so removing
+/// code never hides a compile error.
+pub fn optimize(statements: Vec<Statement>) -> Vec<Statement> {
+    return statements.into_iter().filter_map(statement).collect();
+}
+
+/// Folds the constant parts of a single expression.
+pub fn optimize_expression(expression: Expression) -> Expression {
+    match expression {
+        Expression::Literal(_) | Expression::Variable(..) => return expression,
+        Expression::This(..) | Expression::Super(..) => return expression,
+        Expression::Grouping(inner) => match optimize_expression(*inner) {
+            literal @ Expression::Literal(_) => return literal,
+            inner => return Expression::Grouping(Box::new(inner)),
+        },
+        Expression::Unary(operator, right) => {
+            let right = optimize_expression(*right);
+            if let Expression::Literal(value) = &right {
+                if let Some(folded) = fold_unary(&operator, value) {
+                    return Expression::Literal(folded);
+                }
+            }
+            return Expression::Unary(operator, Box::new(right));
+        }
+        Expression::Binary(left, operator, right) => {
+            let left = optimize_expression(*left);
+            let right = optimize_expression(*right);
+            if let (Expression::Literal(lhs), Expression::Literal(rhs)) = (&left, &right) {
+                if let Some(folded) = fold_binary(lhs, &operator, rhs) {
+                    return Expression::Literal(folded);
+                }
+            }
+            return Expression::Binary(Box::new(left), operator, Box::new(right));
+        }
+        Expression::Logical(left, operator, right) => {
+            let left = optimize_expression(*left);
+            let right = optimize_expression(*right);
+            if let Expression::Literal(value) = &left {
+                // `or` keeps a truthy left operand and `and` a falsey one;
+                // otherwise the result is whatever the right operand yields.
+                let short_circuits =
+                    is_truthy(&value._type) == matches!(operator._type, TokenType::Or);
+                if short_circuits {
+                    return left;
+                }
+                return right;
+            }
+            return Expression::Logical(Box::new(left), operator, Box::new(right));
+        }
+        Expression::Assign(id, name, value) => {
+            return Expression::Assign(id, name, Box::new(optimize_expression(*value)));
+        }
+        Expression::Call(callee, paren, arguments) => {
+            let arguments = arguments.into_iter().map(optimize_expression).collect();
+            return Expression::Call(Box::new(optimize_expression(*callee)), paren, arguments);
+        }
+        Expression::Get(object, name) => {
+            return Expression::Get(Box::new(optimize_expression(*object)), name);
+        }
+        Expression::Set(object, name, value) => {
+            let object = optimize_expression(*object);
+            let value = optimize_expression(*value);
+            return Expression::Set(Box::new(object), name, Box::new(value));
+        }
+    }
+}
+
+/// Optimizes one statement, or returns `None` if it can never do anything.
+fn statement(statement: Statement) -> Option<Statement> {
+    match statement {
+        Statement::Expression(expression) => {
+            return Some(Statement::Expression(optimize_expression(expression)));
+        }
+        Statement::Print(expression) => {
+            return Some(Statement::Print(optimize_expression(expression)));
+        }
+        Statement::Var(name, initializer) => {
+            return Some(Statement::Var(name, initializer.map(optimize_expression)));
+        }
+        Statement::Block(statements) => return Some(Statement::Block(optimize(statements))),
+        Statement::If(condition, then_branch, else_branch) => {
+            let condition = optimize_expression(condition);
+            if let Expression::Literal(value) = &condition {
+                // Branch bodies are statements, not declarations, so taking
+                // one out of the `if` can't change what's in scope.
+                let taken = if is_truthy(&value._type) {
+                    Some(then_branch)
+                } else {
+                    else_branch
+                };
+                return taken.and_then(|branch| self::statement(*branch));
+            }
+            let then_branch = branch(*then_branch);
+            let else_branch = else_branch.map(|else_branch| Box::new(branch(*else_branch)));
+            return Some(Statement::If(condition, Box::new(then_branch), else_branch));
+        }
+        Statement::While(condition, body) => {
+            let condition = optimize_expression(condition);
+            if let Expression::Literal(value) = &condition {
+                if !is_truthy(&value._type) {
+                    return None;
+                }
+            }
+            return Some(Statement::While(condition, Box::new(branch(*body))));
+        }
+        Statement::Function(declaration) => {
+            return Some(Statement::Function(function(declaration)))
+        }
+        Statement::Return(keyword, value) => {
+            return Some(Statement::Return(keyword, value.map(optimize_expression)));
+        }
+        Statement::Class(name, superclass, methods) => {
+            let methods = methods.into_iter().map(function).collect();
+            return Some(Statement::Class(name, superclass, methods));
+        }
+    }
+}
+
+/// Optimizes the body of an `if` or loop, which needs some statement even if
+/// it was optimized away.
+fn branch(body: Statement) -> Statement {
+    return statement(body).unwrap_or_else(|| Statement::Block(Vec::new()));
+}
+
+fn function(declaration: Rc<FunctionDeclaration>) -> Rc<FunctionDeclaration> {
+    match Rc::try_unwrap(declaration) {
+        Ok(FunctionDeclaration { name, params, body }) => {
+            return Rc::new(FunctionDeclaration {
+                name,
+                params,
+                body: optimize(body),
+            });
+        }
+        // Already shared with a running program, so leave it as it is.
+        Err(declaration) => return declaration,
+    }
+}
+
+fn fold_unary(operator: &Token, right: &Token) -> Option<Token> {
+    let folded = match (&operator._type, &right._type) {
+        (TokenType::Minus, TokenType::Number(n)) => TokenType::Number(-n),
+        (TokenType::Bang, value) => boolean(!is_truthy(value)),
+        _ => return None,
+    };
+    return Some(literal(folded, operator, right));
+}
+
+fn fold_binary(left: &Token, operator: &Token, right: &Token) -> Option<Token> {
+    let folded = match (&left._type, &operator._type, &right._type) {
+        (_, TokenType::EqualEqual, _) => boolean(is_equal(&left._type, &right._type)),
+        (_, TokenType::BangEqual, _) => boolean(!is_equal(&left._type, &right._type)),
+        (TokenType::Number(n), operator, TokenType::Number(m)) => match operator {
+            TokenType::Plus => TokenType::Number(n + m),
+            TokenType::Minus => TokenType::Number(n - m),
+            TokenType::Star => TokenType::Number(n * m),
+            TokenType::Slash => TokenType::Number(n / m),
+            TokenType::Greater => boolean(n > m),
+            TokenType::GreaterEqual => boolean(n >= m),
+            TokenType::Less => boolean(n < m),
+            TokenType::LessEqual => boolean(n <= m),
+            _ => return None,
+        },
+        (TokenType::StringLiteral(s), TokenType::Plus, TokenType::StringLiteral(t)) => {
+            TokenType::StringLiteral(Symbol::new(&format!("{}{}", s, t)))
+        }
+        _ => return None,
+    };
+    return Some(literal(folded, left, right));
+}
+
+/// Builds the literal token replacing the expression from `first` to `last`.
+fn literal(_type: TokenType, first: &Token, last: &Token) -> Token {
+    let lexeme = match &_type {
+        TokenType::Number(n) => n.to_string(),
+        TokenType::StringLiteral(s) => format!("\"{}\"", s),
+        TokenType::True => "true".to_string(),
+        TokenType::False => "false".to_string(),
+        _ => unreachable!("folding only produces numbers, strings and booleans"),
+    };
+    return Token {
+        _type,
+        lexeme: Symbol::new(&lexeme),
+        line: first.line,
+        column: first.column,
+        span: first.span.to(last.span),
+    };
+}
+
+fn boolean(value: bool) -> TokenType {
+    return if value {
+        TokenType::True
+    } else {
+        TokenType::False
+    };
+}
+
+fn is_truthy(value: &TokenType) -> bool {
+    return !matches!(value, TokenType::Nil | TokenType::False);
+}
+
+fn is_equal(left: &TokenType, right: &TokenType) -> bool {
+    match (left, right) {
+        (TokenType::Nil, TokenType::Nil) => return true,
+        (TokenType::True, TokenType::True) | (TokenType::False, TokenType::False) => return true,
+        (TokenType::Number(n), TokenType::Number(m)) => return n == m,
+        (TokenType::StringLiteral(s), TokenType::StringLiteral(t)) => return s == t,
+        _ => return false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, LoxError, RuntimeError, Vm};
+
+    /// The literal `source` folds to, or `None` if some of it is left for
+    /// runtime.
+    fn fold(source: &str) -> Option<String> {
+        let expression = crate::parse_expression(source).unwrap();
+        match optimize_expression(expression) {
+            Expression::Literal(token) => return Some(token.lexeme.to_string()),
+            _ => return None,
+        }
+    }
+
+    fn statements(source: &str) -> usize {
+        return optimize(crate::parse_program(source).unwrap()).len();
+    }
+
+    #[test]
+    fn folds_constant_expressions() {
+        assert_eq!(fold("1 + 2 * 3").as_deref(), Some("7"));
+        assert_eq!(fold("(1 + 2) * -3").as_deref(), Some("-9"));
+        assert_eq!(fold("\"a\" + \"b\" + \"c\"").as_deref(), Some("\"abc\""));
+        assert_eq!(fold("1 < 2 == !nil").as_deref(), Some("true"));
+        assert_eq!(fold("\"a\" != \"a\"").as_deref(), Some("false"));
+        assert_eq!(fold("nil or 2").as_deref(), Some("2"));
+        assert_eq!(fold("false and x").as_deref(), Some("false"));
+    }
+
+    #[test]
+    fn leaves_runtime_values_and_errors_alone() {
+        assert_eq!(fold("x + 1"), None);
+        assert_eq!(fold("true and x"), None);
+        assert_eq!(fold("-\"a\""), None);
+        assert_eq!(fold("\"a\" + 1"), None);
+        assert_eq!(fold("1 + nil"), None);
+    }
+
+    #[test]
+    fn drops_branches_that_never_run() {
+        assert_eq!(statements("if (false) print 1;"), 0);
+        assert_eq!(statements("while (nil) print 1;"), 0);
+        assert_eq!(statements("if (1 > 2) print 1; else print 2;"), 1);
+        assert_eq!(statements("if (x) print 1;"), 1);
+    }
+
+    /// The line of the runtime error `source` stops with on either backend,
+    /// with optimization on.
+    fn error_lines(source: &str) -> (usize, usize) {
+        let line = |result: Result<(), LoxError>| match result {
+            Err(LoxError::Runtime(error)) => return error.location().0,
+            _ => panic!("expected a runtime error"),
+        };
+        let tree = line(Interpreter::new().run(source));
+        let vm = line(Vm::new().run(source));
+        return (tree, vm);
+    }
+
+    #[test]
+    fn runtime_errors_still_raise_on_their_line() {
+        assert_eq!(error_lines("var x = 1;\nprint -\"a\";"), (2, 2));
+        assert_eq!(error_lines("var x = 1;\n\nprint\n\"a\" + 1;"), (4, 4));
+        // The left operand folds to "ab", but the addition that fails stays.
+        assert_eq!(error_lines("print \"a\" + \"b\"\n+ 1;"), (2, 2));
+
+        let source = "print 1;\nprint -\"a\";";
+        assert!(matches!(
+            Interpreter::new().run(source),
+            Err(LoxError::Runtime(RuntimeError::OperandMustBeNumber(..)))
+        ));
+    }
+}
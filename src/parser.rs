@@ -0,0 +1,654 @@
+use std::mem;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use thiserror::Error;
+
+use crate::ast::{Expression, FunctionDeclaration, Statement};
+use crate::intern::Symbol;
+use crate::scanner::{ScanError, Span, Token, TokenType};
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error(transparent)]
+    Scan(#[from] ScanError),
+    #[error("[line {}] Error{}: {message}", .token.line, location(.token))]
+    Syntax { token: Token, message: String },
+    #[error("[line {}] Error{}: {message}", .token.line, location(.token))]
+    Resolution { token: Token, message: String },
+    #[error("[line {}] Error{}: {message}", .token.line, location(.token))]
+    Compile { token: Token, message: String },
+}
+
+impl ParseError {
+    pub fn location(&self) -> (usize, usize, Span) {
+        match self {
+            ParseError::Scan(error) => error.location(),
+            ParseError::Syntax { token, .. }
+            | ParseError::Resolution { token, .. }
+            | ParseError::Compile { token, .. } => (token.line, token.column, token.span),
+        }
+    }
+
+    /// What went wrong, without the line and token the full message starts
+    /// with.
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::Scan(error) => error.message(),
+            ParseError::Syntax { message, .. }
+            | ParseError::Resolution { message, .. }
+            | ParseError::Compile { message, .. } => message.clone(),
+        }
+    }
+}
+
+fn location(token: &Token) -> String {
+    match token._type {
+        TokenType::Eof => " at end".to_string(),
+        _ => format!(" at '{}'", token.lexeme),
+    }
+}
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    current: usize,
+    errors: Vec<ParseError>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+pub type ParserResult = Result<Expression, ParseError>;
+
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Parser {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Hands out ids for the expressions the resolver binds to a scope depth.
+    /// The counter is global so ids stay unique across separately parsed sources.
+    fn next_id() -> usize {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        return NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn parse(&mut self) -> ParserResult {
+        let expression = self.expression();
+        return self.first_error(expression);
+    }
+
+    /// Parses input that is a single expression and nothing else, as typed at the REPL.
+    pub fn parse_bare_expression(&mut self) -> ParserResult {
+        let expression = self.expression();
+        let expression = self.first_error(expression)?;
+        if !self.is_at_end() {
+            return Err(Parser::error(
+                self.peek().clone(),
+                "Expect end of expression.".to_string(),
+            ));
+        }
+        return Ok(expression);
+    }
+
+    /// Fails with the first error recorded without unwinding, such as an
+    /// invalid assignment target, since it comes before any error that did
+    /// unwind.
+    fn first_error(&mut self, result: ParserResult) -> ParserResult {
+        if let Some(error) = mem::take(&mut self.errors).into_iter().next() {
+            return Err(error);
+        }
+        return result;
+    }
+
+    pub fn parse_program(&mut self) -> Result<Vec<Statement>, Vec<ParseError>> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+        if self.errors.is_empty() {
+            return Ok(statements);
+        }
+        return Err(mem::take(&mut self.errors));
+    }
+
+    /// Parses one declaration, recording any syntax error and skipping ahead to
+    /// the next statement boundary so parsing can carry on.
+    fn declaration(&mut self) -> Option<Statement> {
+        match self.try_declaration() {
+            Ok(statement) => return Some(statement),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                return None;
+            }
+        }
+    }
+
+    fn try_declaration(&mut self) -> Result<Statement, ParseError> {
+        if matches!(self.peek()._type, TokenType::Class) {
+            self.advance();
+            return self.class_declaration();
+        }
+        if matches!(self.peek()._type, TokenType::Fun) {
+            self.advance();
+            return Ok(Statement::Function(self.function(FunctionKind::Function)?));
+        }
+        if matches!(self.peek()._type, TokenType::Var) {
+            self.advance();
+            return self.var_declaration();
+        }
+        return self.statement();
+    }
+
+    fn class_declaration(&mut self) -> Result<Statement, ParseError> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.")?;
+
+        let mut superclass = None;
+        if matches!(self.peek()._type, TokenType::Less) {
+            self.advance();
+            let superclass_name = self.consume(TokenType::Identifier, "Expect superclass name.")?;
+            superclass = Some(Expression::Variable(Parser::next_id(), superclass_name));
+        }
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.function(FunctionKind::Method)?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+        return Ok(Statement::Class(name, superclass, methods));
+    }
+
+    fn function(&mut self, kind: FunctionKind) -> Result<Rc<FunctionDeclaration>, ParseError> {
+        let label = match kind {
+            FunctionKind::Method => "method",
+            _ => "function",
+        };
+        let name = self.consume(TokenType::Identifier, &format!("Expect {} name.", label))?;
+        self.consume(
+            TokenType::LeftParen,
+            &format!("Expect '(' after {} name.", label),
+        )?;
+
+        let mut params = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    let error = Parser::error(
+                        self.peek().clone(),
+                        "Can't have more than 255 parameters.".to_string(),
+                    );
+                    self.errors.push(error);
+                }
+                params.push(self.consume(TokenType::Identifier, "Expect parameter name.")?);
+
+                if !matches!(self.peek()._type, TokenType::Comma) {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(
+            TokenType::LeftBrace,
+            &format!("Expect '{{' before {} body.", label),
+        )?;
+        let body = self.block()?;
+
+        return Ok(Rc::new(FunctionDeclaration { name, params, body }));
+    }
+
+    fn var_declaration(&mut self) -> Result<Statement, ParseError> {
+        let name = self.consume(TokenType::Identifier, "Expect variable name.")?;
+
+        let mut initializer = None;
+        if matches!(self.peek()._type, TokenType::Equal) {
+            self.advance();
+            initializer = Some(self.expression()?);
+        }
+
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        )?;
+        return Ok(Statement::Var(name, initializer));
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        if matches!(self.peek()._type, TokenType::For) {
+            self.advance();
+            return self.for_statement();
+        }
+        if matches!(self.peek()._type, TokenType::If) {
+            self.advance();
+            return self.if_statement();
+        }
+        if matches!(self.peek()._type, TokenType::While) {
+            self.advance();
+            return self.while_statement();
+        }
+        if matches!(self.peek()._type, TokenType::Print) {
+            self.advance();
+            return self.print_statement();
+        }
+        if matches!(self.peek()._type, TokenType::Return) {
+            self.advance();
+            return self.return_statement();
+        }
+        if matches!(self.peek()._type, TokenType::LeftBrace) {
+            self.advance();
+            return Ok(Statement::Block(self.block()?));
+        }
+        return self.expression_statement();
+    }
+
+    fn for_statement(&mut self) -> Result<Statement, ParseError> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if matches!(self.peek()._type, TokenType::Semicolon) {
+            self.advance();
+            None
+        } else if matches!(self.peek()._type, TokenType::Var) {
+            self.advance();
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let mut condition = None;
+        if !self.check(&TokenType::Semicolon) {
+            condition = Some(self.expression()?);
+        }
+        let semicolon = self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
+
+        let mut increment = None;
+        if !self.check(&TokenType::RightParen) {
+            increment = Some(self.expression()?);
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        // Desugar into a while loop wrapped in blocks for the increment and initializer.
+        let mut body = self.statement()?;
+        if let Some(increment) = increment {
+            body = Statement::Block(vec![body, Statement::Expression(increment)]);
+        }
+        // A missing condition becomes a zero-width `true` where the condition would be.
+        let condition = condition.unwrap_or_else(|| {
+            Expression::Literal(Token {
+                _type: TokenType::True,
+                lexeme: Symbol::new("true"),
+                line: semicolon.line,
+                column: semicolon.column,
+                span: Span::new(semicolon.span.start, semicolon.span.start),
+            })
+        });
+        body = Statement::While(condition, Box::new(body));
+        if let Some(initializer) = initializer {
+            body = Statement::Block(vec![initializer, body]);
+        }
+
+        return Ok(body);
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, ParseError> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = self.statement()?;
+        let mut else_branch = None;
+        if matches!(self.peek()._type, TokenType::Else) {
+            self.advance();
+            else_branch = Some(Box::new(self.statement()?));
+        }
+
+        return Ok(Statement::If(condition, Box::new(then_branch), else_branch));
+    }
+
+    fn while_statement(&mut self) -> Result<Statement, ParseError> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = self.statement()?;
+
+        return Ok(Statement::While(condition, Box::new(body)));
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let mut statements = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        return Ok(statements);
+    }
+
+    fn print_statement(&mut self) -> Result<Statement, ParseError> {
+        let value = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        return Ok(Statement::Print(value));
+    }
+
+    fn return_statement(&mut self) -> Result<Statement, ParseError> {
+        let keyword = self.previous();
+        let mut value = None;
+        if !self.check(&TokenType::Semicolon) {
+            value = Some(self.expression()?);
+        }
+
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        return Ok(Statement::Return(keyword, value));
+    }
+
+    fn expression_statement(&mut self) -> Result<Statement, ParseError> {
+        let expression = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+        return Ok(Statement::Expression(expression));
+    }
+
+    fn expression(&mut self) -> ParserResult {
+        return self.assignment();
+    }
+
+    fn assignment(&mut self) -> ParserResult {
+        let expression = self.or()?;
+
+        if matches!(self.peek()._type, TokenType::Equal) {
+            let equals = self.advance();
+            let value = self.assignment()?;
+
+            match expression {
+                Expression::Variable(_, name) => {
+                    return Ok(Expression::Assign(Parser::next_id(), name, Box::new(value)));
+                }
+                Expression::Get(object, name) => {
+                    return Ok(Expression::Set(object, name, Box::new(value)));
+                }
+                _ => {}
+            }
+            // The parser is not confused here, so report without unwinding.
+            let error = Parser::error(equals, "Invalid assignment target.".to_string());
+            self.errors.push(error);
+        }
+
+        return Ok(expression);
+    }
+
+    fn or(&mut self) -> ParserResult {
+        let mut lhs = self.and()?;
+
+        while matches!(self.peek()._type, TokenType::Or) {
+            let operator = self.advance();
+            let rhs = self.and()?;
+            lhs = Expression::Logical(Box::new(lhs), operator, Box::new(rhs));
+        }
+
+        return Ok(lhs);
+    }
+
+    fn and(&mut self) -> ParserResult {
+        let mut lhs = self.equality()?;
+
+        while matches!(self.peek()._type, TokenType::And) {
+            let operator = self.advance();
+            let rhs = self.equality()?;
+            lhs = Expression::Logical(Box::new(lhs), operator, Box::new(rhs));
+        }
+
+        return Ok(lhs);
+    }
+
+    fn equality(&mut self) -> ParserResult {
+        let mut lhs = self.comparison()?;
+
+        while matches!(
+            self.peek()._type,
+            TokenType::BangEqual | TokenType::EqualEqual
+        ) {
+            let operator = self.advance();
+            let rhs = self.comparison()?;
+            lhs = Expression::Binary(Box::new(lhs), operator, Box::new(rhs));
+        }
+
+        return Ok(lhs);
+    }
+
+    fn comparison(&mut self) -> ParserResult {
+        let mut lhs = self.term()?;
+
+        while matches!(
+            self.peek()._type,
+            TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual
+        ) {
+            let operator = self.advance();
+            let rhs = self.term()?;
+            lhs = Expression::Binary(Box::new(lhs), operator, Box::new(rhs));
+        }
+
+        return Ok(lhs);
+    }
+
+    fn term(&mut self) -> ParserResult {
+        let mut lhs = self.factor()?;
+
+        while matches!(self.peek()._type, TokenType::Minus | TokenType::Plus) {
+            let operator = self.advance();
+            let rhs = self.factor()?;
+            lhs = Expression::Binary(Box::new(lhs), operator, Box::new(rhs));
+        }
+
+        return Ok(lhs);
+    }
+
+    fn factor(&mut self) -> ParserResult {
+        let mut left = self.unary()?;
+
+        while matches!(self.peek()._type, TokenType::Slash | TokenType::Star) {
+            let operator = self.advance();
+            let right = self.unary()?;
+            left = Expression::Binary(Box::new(left), operator, Box::new(right));
+        }
+
+        return Ok(left);
+    }
+
+    fn unary(&mut self) -> ParserResult {
+        if matches!(self.peek()._type, TokenType::Bang | TokenType::Minus) {
+            let operator = self.advance();
+            let right = self.unary()?;
+
+            return Ok(Expression::Unary(operator, Box::new(right)));
+        }
+
+        return self.call();
+    }
+
+    fn call(&mut self) -> ParserResult {
+        let mut expression = self.primary()?;
+
+        loop {
+            if matches!(self.peek()._type, TokenType::LeftParen) {
+                self.advance();
+                expression = self.finish_call(expression)?;
+            } else if matches!(self.peek()._type, TokenType::Dot) {
+                self.advance();
+                let name =
+                    self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
+                expression = Expression::Get(Box::new(expression), name);
+            } else {
+                break;
+            }
+        }
+
+        return Ok(expression);
+    }
+
+    fn finish_call(&mut self, callee: Expression) -> ParserResult {
+        let mut arguments = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= 255 {
+                    let error = Parser::error(
+                        self.peek().clone(),
+                        "Can't have more than 255 arguments.".to_string(),
+                    );
+                    self.errors.push(error);
+                }
+                arguments.push(self.expression()?);
+
+                if !matches!(self.peek()._type, TokenType::Comma) {
+                    break;
+                }
+                self.advance();
+            }
+        }
+
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+        return Ok(Expression::Call(Box::new(callee), paren, arguments));
+    }
+
+    fn primary(&mut self) -> ParserResult {
+        if matches!(
+            self.peek()._type,
+            TokenType::False
+                | TokenType::True
+                | TokenType::Nil
+                | TokenType::Number(_)
+                | TokenType::StringLiteral(_)
+        ) {
+            return Ok(Expression::Literal(self.advance()));
+        }
+
+        if matches!(self.peek()._type, TokenType::Super) {
+            let keyword = self.advance();
+            self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+            let method = self.consume(TokenType::Identifier, "Expect superclass method name.")?;
+            return Ok(Expression::Super(Parser::next_id(), keyword, method));
+        }
+
+        if matches!(self.peek()._type, TokenType::This) {
+            return Ok(Expression::This(Parser::next_id(), self.advance()));
+        }
+
+        if matches!(self.peek()._type, TokenType::Identifier) {
+            return Ok(Expression::Variable(Parser::next_id(), self.advance()));
+        }
+
+        if matches!(self.peek()._type, TokenType::LeftParen) {
+            self.advance();
+            let expression = self.expression()?;
+
+            if !matches!(self.peek()._type, TokenType::RightParen) {
+                return Err(Parser::error(
+                    self.peek().clone(),
+                    "Expect ')' after expression".to_string(),
+                ));
+            }
+            self.advance();
+            return Ok(Expression::Grouping(Box::new(expression)));
+        }
+        return Err(Parser::error(
+            self.peek().clone(),
+            "Expect expression.".to_string(),
+        ));
+    }
+
+    fn error(token: Token, message: String) -> ParseError {
+        return ParseError::Syntax { token, message };
+    }
+
+    fn synchronize(&mut self) {
+        self.advance();
+
+        while !self.is_at_end() {
+            if matches!(self.previous()._type, TokenType::Semicolon) {
+                return;
+            }
+
+            if matches!(
+                self.peek()._type,
+                TokenType::Class
+                    | TokenType::Fun
+                    | TokenType::Var
+                    | TokenType::For
+                    | TokenType::If
+                    | TokenType::While
+                    | TokenType::Print
+                    | TokenType::Return
+            ) {
+                return;
+            }
+
+            self.advance();
+        }
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<Token, ParseError> {
+        if self.check(&token_type) {
+            return Ok(self.advance());
+        }
+        return Err(Parser::error(self.peek().clone(), message.to_string()));
+    }
+
+    fn check(&self, token_type: &TokenType) -> bool {
+        return mem::discriminant(&self.peek()._type) == mem::discriminant(token_type);
+    }
+
+    fn advance(&mut self) -> Token {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        return self.previous();
+    }
+
+    pub(crate) fn is_at_end(&self) -> bool {
+        return matches!(self.peek()._type, TokenType::Eof);
+    }
+
+    fn peek(&self) -> &Token {
+        return &self.tokens[self.current];
+    }
+
+    fn previous(&self) -> Token {
+        return self.tokens[self.current - 1].clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_at_statement_boundaries_to_report_every_error() {
+        let (tokens, _) =
+            crate::Scanner::scan("print 1 +;\nvar = 2;\nprint (3;\nprint 4;\n".to_string());
+        let Err(errors) = Parser::new(tokens).parse_program() else {
+            panic!("expected syntax errors");
+        };
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "[line 1] Error at ';': Expect expression.",
+                "[line 2] Error at '=': Expect variable name.",
+                "[line 3] Error at ';': Expect ')' after expression",
+            ]
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! The framing the Language Server Protocol and the Debug Adapter Protocol
+//! share: each message is a JSON body after a `Content-Length` header.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// Reads the body of the next message, or `None` at the end of `input`.
+pub(crate) fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length header",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    return Ok(Some(body));
+}
+
+/// Sends `message` with the header that frames it.
+pub(crate) fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    return output.flush();
+}
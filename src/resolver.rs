@@ -0,0 +1,285 @@
+use std::mem;
+
+use crate::ast::{Expression, FunctionDeclaration, Statement};
+use crate::intern::Symbol;
+use crate::interpreter::Interpreter;
+use crate::parser::{FunctionKind, ParseError};
+use crate::scanner::Token;
+use crate::table::Table;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+/// Static pass that binds every local variable reference to the number of
+/// scopes between it and its declaration, reporting scoping mistakes early.
+pub struct Resolver<'a> {
+    interpreter: &'a mut Interpreter,
+    scopes: Vec<Table<bool>>,
+    current_function: FunctionKind,
+    current_class: ClassKind,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(interpreter: &'a mut Interpreter) -> Self {
+        Resolver {
+            interpreter,
+            scopes: Vec::new(),
+            current_function: FunctionKind::None,
+            current_class: ClassKind::None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve(mut self, statements: &[Statement]) -> Result<(), Vec<ParseError>> {
+        self.resolve_statements(statements);
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        return Err(self.errors);
+    }
+
+    pub fn resolve_bare_expression(
+        mut self,
+        expression: &Expression,
+    ) -> Result<(), Vec<ParseError>> {
+        self.resolve_expression(expression);
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        return Err(self.errors);
+    }
+
+    fn resolve_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression) | Statement::Print(expression) => {
+                self.resolve_expression(expression);
+            }
+            Statement::Var(name, initializer) => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.resolve_expression(initializer);
+                }
+                self.define(name);
+            }
+            Statement::Block(statements) => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                self.end_scope();
+            }
+            Statement::If(condition, then_branch, else_branch) => {
+                self.resolve_expression(condition);
+                self.resolve_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(else_branch);
+                }
+            }
+            Statement::While(condition, body) => {
+                self.resolve_expression(condition);
+                self.resolve_statement(body);
+            }
+            Statement::Function(declaration) => {
+                self.declare(&declaration.name);
+                self.define(&declaration.name);
+                self.resolve_function(declaration, FunctionKind::Function);
+            }
+            Statement::Return(keyword, value) => {
+                if self.current_function == FunctionKind::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    if self.current_function == FunctionKind::Initializer {
+                        self.error(keyword, "Can't return a value from an initializer.");
+                    }
+                    self.resolve_expression(value);
+                }
+            }
+            Statement::Class(name, superclass, methods) => {
+                let enclosing_class = mem::replace(&mut self.current_class, ClassKind::Class);
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    if let Expression::Variable(_, superclass_name) = superclass {
+                        if superclass_name.lexeme == name.lexeme {
+                            self.error(superclass_name, "A class can't inherit from itself.");
+                        }
+                    }
+                    self.current_class = ClassKind::Subclass;
+                    self.resolve_expression(superclass);
+
+                    self.begin_scope();
+                    self.scope_define("super");
+                }
+
+                self.begin_scope();
+                self.scope_define("this");
+                for method in methods {
+                    let kind = if method.name.lexeme == "init" {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+                    self.resolve_function(method, kind);
+                }
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+                self.current_class = enclosing_class;
+            }
+        }
+    }
+
+    fn resolve_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Literal(_) => {}
+            Expression::Unary(_, right) => self.resolve_expression(right),
+            Expression::Binary(left, _, right) | Expression::Logical(left, _, right) => {
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            Expression::Grouping(expression) => self.resolve_expression(expression),
+            Expression::Variable(id, name) => {
+                let declared = self.scopes.last().and_then(|scope| scope.get(&name.lexeme));
+                if declared == Some(&false) {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+                self.resolve_local(*id, name);
+            }
+            Expression::Assign(id, name, value) => {
+                self.resolve_expression(value);
+                self.resolve_local(*id, name);
+            }
+            Expression::Call(callee, _, arguments) => {
+                self.resolve_expression(callee);
+                for argument in arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            Expression::Get(object, _) => self.resolve_expression(object),
+            Expression::Set(object, _, value) => {
+                self.resolve_expression(value);
+                self.resolve_expression(object);
+            }
+            Expression::This(id, keyword) => {
+                if self.current_class == ClassKind::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                    return;
+                }
+                self.resolve_local(*id, keyword);
+            }
+            Expression::Super(id, keyword, _) => {
+                match self.current_class {
+                    ClassKind::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                    ClassKind::Class => {
+                        self.error(keyword, "Can't use 'super' in a class with no superclass.")
+                    }
+                    ClassKind::Subclass => {}
+                }
+                self.resolve_local(*id, keyword);
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, declaration: &FunctionDeclaration, kind: FunctionKind) {
+        let enclosing_function = mem::replace(&mut self.current_function, kind);
+
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(&declaration.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_local(&mut self, id: usize, name: &Token) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(&name.lexeme) {
+                self.interpreter.resolve(id, depth);
+                return;
+            }
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Table::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        if scope.contains_key(&name.lexeme) {
+            self.error(name, "Already a variable with this name in this scope.");
+            return;
+        }
+        scope.insert(name.lexeme.clone(), false);
+    }
+
+    fn define(&mut self, name: &Token) {
+        self.scope_define(&name.lexeme);
+    }
+
+    fn scope_define(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(Symbol::new(name), true);
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ParseError::Resolution {
+            token: token.clone(),
+            message: message.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(source: &str) -> Vec<String> {
+        let statements = crate::parse_program(source).unwrap();
+        let mut interpreter = Interpreter::new();
+        match Resolver::new(&mut interpreter).resolve(&statements) {
+            Ok(()) => return Vec::new(),
+            Err(errors) => return errors.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn reports_every_misused_name_or_keyword() {
+        assert_eq!(
+            errors("{ var a = a; }\nreturn 1;\nprint this;\n"),
+            [
+                "[line 1] Error at 'a': Can't read local variable in its own initializer.",
+                "[line 2] Error at 'return': Can't return from top-level code.",
+                "[line 3] Error at 'this': Can't use 'this' outside of a class.",
+            ]
+        );
+    }
+
+    #[test]
+    fn globals_may_refer_to_themselves() {
+        assert!(errors("var a = 1;\nvar a = a;\nfun f() { return f; }\n").is_empty());
+    }
+}
@@ -0,0 +1,735 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::intern::Symbol;
+
+#[derive(Clone, Debug)]
+pub enum TokenType {
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+
+    Comma,
+    Dot,
+    Minus,
+    Plus,
+    Semicolon,
+    Slash,
+    Star,
+
+    Equal,
+    EqualEqual,
+    Bang,
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+
+    Identifier,
+
+    StringLiteral(Symbol),
+    Number(f64),
+
+    And,
+    Class,
+    Else,
+    False,
+    Fun,
+    For,
+    If,
+    Nil,
+    Or,
+    Print,
+    Return,
+    Super,
+    This,
+    True,
+    Var,
+    While,
+
+    Eof,
+}
+
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TokenType::LeftParen => write!(f, "LEFT_PAREN"),
+            TokenType::RightParen => write!(f, "RIGHT_PAREN"),
+            TokenType::LeftBrace => write!(f, "LEFT_BRACE"),
+            TokenType::RightBrace => write!(f, "RIGHT_BRACE"),
+
+            TokenType::Comma => write!(f, "COMMA"),
+            TokenType::Dot => write!(f, "DOT"),
+            TokenType::Minus => write!(f, "MINUS"),
+            TokenType::Plus => write!(f, "PLUS"),
+            TokenType::Semicolon => write!(f, "SEMICOLON"),
+            TokenType::Slash => write!(f, "SLASH"),
+            TokenType::Star => write!(f, "STAR"),
+
+            TokenType::Equal => write!(f, "EQUAL"),
+            TokenType::EqualEqual => write!(f, "EQUAL_EQUAL"),
+            TokenType::Bang => write!(f, "BANG"),
+            TokenType::BangEqual => write!(f, "BANG_EQUAL"),
+            TokenType::Less => write!(f, "LESS"),
+            TokenType::LessEqual => write!(f, "LESS_EQUAL"),
+            TokenType::Greater => write!(f, "GREATER"),
+            TokenType::GreaterEqual => write!(f, "GREATER_EQUAL"),
+
+            TokenType::Identifier => write!(f, "IDENTIFIER"),
+
+            TokenType::StringLiteral(_) => write!(f, "STRING"),
+            TokenType::Number(_) => write!(f, "NUMBER"),
+
+            TokenType::And => write!(f, "AND"),
+            TokenType::Class => write!(f, "CLASS"),
+            TokenType::Else => write!(f, "ELSE"),
+            TokenType::False => write!(f, "FALSE"),
+            TokenType::Fun => write!(f, "FUN"),
+            TokenType::For => write!(f, "FOR"),
+            TokenType::If => write!(f, "IF"),
+            TokenType::Nil => write!(f, "NIL"),
+            TokenType::Or => write!(f, "OR"),
+            TokenType::Print => write!(f, "PRINT"),
+            TokenType::Return => write!(f, "RETURN"),
+            TokenType::Super => write!(f, "SUPER"),
+            TokenType::This => write!(f, "THIS"),
+            TokenType::True => write!(f, "TRUE"),
+            TokenType::Var => write!(f, "VAR"),
+            TokenType::While => write!(f, "WHILE"),
+            TokenType::Eof => write!(f, "EOF"),
+        }
+    }
+}
+
+/// A half-open range of byte offsets into the scanned source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub(crate) _type: TokenType,
+    pub(crate) lexeme: Symbol,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    pub(crate) span: Span,
+}
+
+impl Token {
+    pub(crate) fn new(
+        _type: TokenType,
+        lexeme: String,
+        line: usize,
+        column: usize,
+        start: usize,
+    ) -> Self {
+        let span = Span::new(start, start + lexeme.len());
+        Token {
+            _type,
+            lexeme: Symbol::from(lexeme),
+            line,
+            column,
+            span,
+        }
+    }
+
+    pub fn token_type(&self) -> &TokenType {
+        return &self._type;
+    }
+
+    pub fn lexeme(&self) -> &str {
+        return &self.lexeme;
+    }
+
+    pub fn line(&self) -> usize {
+        return self.line;
+    }
+
+    pub fn column(&self) -> usize {
+        return self.column;
+    }
+
+    pub fn span(&self) -> Span {
+        return self.span;
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let literal = match &self._type {
+            TokenType::StringLiteral(value) => value.to_string(),
+            TokenType::Number(value) => {
+                let integer: f64 = (*value as i64) as f64;
+                if integer.to_bits() == value.to_bits() {
+                    format!("{}.0", integer)
+                } else {
+                    value.to_string()
+                }
+            }
+            _ => "null".to_string(),
+        };
+        write!(f, "{} {} {}", self._type, self.lexeme, literal)
+    }
+}
+
+/// Every word [`check_reserved`] doesn't treat as an identifier.
+pub const KEYWORDS: [&str; 16] = [
+    "and", "class", "else", "false", "for", "fun", "if", "nil", "or", "print", "return", "super",
+    "this", "true", "var", "while",
+];
+
+pub fn check_reserved(word: &str) -> TokenType {
+    match word {
+        "and" => TokenType::And,
+        "class" => TokenType::Class,
+        "else" => TokenType::Else,
+        "false" => TokenType::False,
+
+        "for" => TokenType::For,
+        "fun" => TokenType::Fun,
+        "if" => TokenType::If,
+        "nil" => TokenType::Nil,
+
+        "or" => TokenType::Or,
+        "print" => TokenType::Print,
+        "return" => TokenType::Return,
+        "super" => TokenType::Super,
+
+        "this" => TokenType::This,
+        "true" => TokenType::True,
+        "var" => TokenType::Var,
+        "while" => TokenType::While,
+
+        _ => TokenType::Identifier,
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ScanError {
+    #[error("[line {line}] Error: Unexpected character: {character}")]
+    UnexpectedCharacter {
+        line: usize,
+        column: usize,
+        span: Span,
+        character: char,
+    },
+    #[error("[line {line}] Error: Unterminated string.")]
+    UnterminatedString {
+        line: usize,
+        column: usize,
+        span: Span,
+    },
+}
+
+/// A `//` comment. The parser never sees these, but tools that reproduce the
+/// source do.
+#[derive(Clone, Debug)]
+pub struct Comment {
+    pub(crate) text: String,
+    pub(crate) line: usize,
+    pub(crate) span: Span,
+}
+
+impl Comment {
+    /// The comment including its leading `//`, without the line break.
+    pub fn text(&self) -> &str {
+        return &self.text;
+    }
+
+    pub fn line(&self) -> usize {
+        return self.line;
+    }
+
+    pub fn span(&self) -> Span {
+        return self.span;
+    }
+}
+
+/// What a piece of [`Trivia`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces, tabs and carriage returns that don't end a line.
+    Whitespace,
+    /// A single `\n` or `\r\n`.
+    Newline,
+    /// A `//` comment, without the line break after it.
+    Comment,
+    /// Text the scanner rejected, like a stray `$` or an unterminated
+    /// string.
+    Skipped,
+}
+
+/// Source text between tokens that the parser ignores.
+#[derive(Clone, Debug)]
+pub struct Trivia {
+    pub(crate) kind: TriviaKind,
+    pub(crate) text: String,
+}
+
+impl Trivia {
+    pub fn kind(&self) -> TriviaKind {
+        return self.kind;
+    }
+
+    pub fn text(&self) -> &str {
+        return &self.text;
+    }
+}
+
+/// A token together with the trivia around it. Trailing trivia runs up to
+/// the end of the token's line; everything after that leads the next token.
+#[derive(Clone, Debug)]
+pub struct SyntaxToken {
+    pub(crate) token: Token,
+    pub(crate) leading: Vec<Trivia>,
+    pub(crate) trailing: Vec<Trivia>,
+}
+
+impl SyntaxToken {
+    pub fn token(&self) -> &Token {
+        return &self.token;
+    }
+
+    pub fn leading(&self) -> &[Trivia] {
+        return &self.leading;
+    }
+
+    pub fn trailing(&self) -> &[Trivia] {
+        return &self.trailing;
+    }
+
+    /// The token as written, with its leading and trailing trivia.
+    pub fn full_text(&self) -> String {
+        let mut text = String::new();
+        for trivia in &self.leading {
+            text += &trivia.text;
+        }
+        text += &self.token.lexeme;
+        for trivia in &self.trailing {
+            text += &trivia.text;
+        }
+        return text;
+    }
+}
+
+pub struct Scanner {}
+
+impl Scanner {
+    pub fn scan(file_contents: String) -> (Vec<Token>, Vec<ScanError>) {
+        let (tokens, _, errors) = Scanner::scan_with_comments(file_contents);
+        return (tokens, errors);
+    }
+
+    /// Like [`Scanner::scan`], but keeps everything it skipped as trivia on
+    /// the tokens, so concatenating their full text reproduces `source`
+    /// byte for byte, even if it has scan errors.
+    pub fn scan_lossless(source: &str) -> (Vec<SyntaxToken>, Vec<ScanError>) {
+        let (tokens, errors) = Scanner::scan(source.to_string());
+        let mut lossless: Vec<SyntaxToken> = Vec::with_capacity(tokens.len());
+        let mut end = 0;
+        for token in tokens {
+            let mut leading = trivia(&source[end..token.span.start]);
+            if let Some(previous) = lossless.last_mut() {
+                let line_end = leading
+                    .iter()
+                    .position(|trivia| trivia.kind == TriviaKind::Newline)
+                    .unwrap_or(leading.len());
+                previous.trailing = leading.drain(..line_end).collect();
+            }
+            end = token.span.end;
+            lossless.push(SyntaxToken {
+                token,
+                leading,
+                trailing: Vec::new(),
+            });
+        }
+        return (lossless, errors);
+    }
+
+    /// Like [`Scanner::scan`], but also returns the comments it skipped, in
+    /// source order.
+    pub fn scan_with_comments(file_contents: String) -> (Vec<Token>, Vec<Comment>, Vec<ScanError>) {
+        let mut errors = Vec::new();
+        let mut comments = Vec::new();
+        let mut tokens: Vec<Token> = Vec::<Token>::new();
+        let mut line_number = 1;
+        let mut columns = Columns {
+            offset: 0,
+            column: 1,
+        };
+        if !file_contents.is_empty() {
+            let mut file_content_chars = file_contents.char_indices().peekable();
+
+            while let Some((start, char)) = file_content_chars.next() {
+                let column = columns.at(&file_contents, start);
+                match char {
+                    '(' => tokens.push(Token::new(
+                        TokenType::LeftParen,
+                        "(".to_string(),
+                        line_number,
+                        column,
+                        start,
+                    )),
+                    ')' => tokens.push(Token::new(
+                        TokenType::RightParen,
+                        ")".to_string(),
+                        line_number,
+                        column,
+                        start,
+                    )),
+                    '{' => tokens.push(Token::new(
+                        TokenType::LeftBrace,
+                        "{".to_string(),
+                        line_number,
+                        column,
+                        start,
+                    )),
+                    '}' => tokens.push(Token::new(
+                        TokenType::RightBrace,
+                        "}".to_string(),
+                        line_number,
+                        column,
+                        start,
+                    )),
+                    ',' => tokens.push(Token::new(
+                        TokenType::Comma,
+                        ",".to_string(),
+                        line_number,
+                        column,
+                        start,
+                    )),
+                    '.' => tokens.push(Token::new(
+                        TokenType::Dot,
+                        ".".to_string(),
+                        line_number,
+                        column,
+                        start,
+                    )),
+                    '-' => tokens.push(Token::new(
+                        TokenType::Minus,
+                        "-".to_string(),
+                        line_number,
+                        column,
+                        start,
+                    )),
+                    '+' => tokens.push(Token::new(
+                        TokenType::Plus,
+                        "+".to_string(),
+                        line_number,
+                        column,
+                        start,
+                    )),
+                    ';' => tokens.push(Token::new(
+                        TokenType::Semicolon,
+                        ";".to_string(),
+                        line_number,
+                        column,
+                        start,
+                    )),
+                    '*' => tokens.push(Token::new(
+                        TokenType::Star,
+                        "*".to_string(),
+                        line_number,
+                        column,
+                        start,
+                    )),
+                    '=' => match file_content_chars.peek() {
+                        Some((_, '=')) => {
+                            file_content_chars.next();
+                            tokens.push(Token::new(
+                                TokenType::EqualEqual,
+                                "==".to_string(),
+                                line_number,
+                                column,
+                                start,
+                            ));
+                        }
+                        _ => tokens.push(Token::new(
+                            TokenType::Equal,
+                            "=".to_string(),
+                            line_number,
+                            column,
+                            start,
+                        )),
+                    },
+                    '!' => match file_content_chars.peek() {
+                        Some((_, '=')) => {
+                            file_content_chars.next();
+                            tokens.push(Token::new(
+                                TokenType::BangEqual,
+                                "!=".to_string(),
+                                line_number,
+                                column,
+                                start,
+                            ));
+                        }
+                        _ => tokens.push(Token::new(
+                            TokenType::Bang,
+                            "!".to_string(),
+                            line_number,
+                            column,
+                            start,
+                        )),
+                    },
+                    '<' => match file_content_chars.peek() {
+                        Some((_, '=')) => {
+                            file_content_chars.next();
+                            tokens.push(Token::new(
+                                TokenType::LessEqual,
+                                "<=".to_string(),
+                                line_number,
+                                column,
+                                start,
+                            ));
+                        }
+                        _ => tokens.push(Token::new(
+                            TokenType::Less,
+                            "<".to_string(),
+                            line_number,
+                            column,
+                            start,
+                        )),
+                    },
+                    '>' => match file_content_chars.peek() {
+                        Some((_, '=')) => {
+                            file_content_chars.next();
+                            tokens.push(Token::new(
+                                TokenType::GreaterEqual,
+                                ">=".to_string(),
+                                line_number,
+                                column,
+                                start,
+                            ));
+                        }
+                        _ => tokens.push(Token::new(
+                            TokenType::Greater,
+                            ">".to_string(),
+                            line_number,
+                            column,
+                            start,
+                        )),
+                    },
+                    '/' => match file_content_chars.peek() {
+                        Some((_, '/')) => {
+                            let line = line_number;
+                            let mut end = file_contents.len();
+                            for (index, next_char) in file_content_chars.by_ref() {
+                                if next_char == '\n' {
+                                    end = index;
+                                    line_number += 1;
+                                    columns.new_line(index + 1);
+                                    break;
+                                }
+                            }
+                            let text = file_contents[start..end].trim_end_matches('\r');
+                            comments.push(Comment {
+                                text: text.to_string(),
+                                line,
+                                span: Span::new(start, start + text.len()),
+                            });
+                        }
+                        _ => tokens.push(Token::new(
+                            TokenType::Slash,
+                            "/".to_string(),
+                            line_number,
+                            column,
+                            start,
+                        )),
+                    },
+                    '"' => {
+                        let line = line_number;
+                        let mut string = String::new();
+                        let mut is_closed = false;
+                        for (index, next_char) in file_content_chars.by_ref() {
+                            if next_char == '"' {
+                                is_closed = true;
+                                break;
+                            }
+                            if next_char == '\n' {
+                                line_number += 1;
+                                columns.new_line(index + 1);
+                            }
+                            string.push(next_char);
+                        }
+                        if is_closed {
+                            let mut quoted: String = "\"".to_string();
+                            quoted.push_str(&string);
+                            quoted.push('"');
+                            tokens.push(Token::new(
+                                TokenType::StringLiteral(Symbol::new(&string)),
+                                quoted,
+                                line,
+                                column,
+                                start,
+                            ));
+                        } else {
+                            errors.push(ScanError::UnterminatedString {
+                                line,
+                                column,
+                                span: Span::new(start, file_contents.len()),
+                            });
+                        }
+                    }
+                    char if char.is_numeric() => {
+                        let mut string = String::new();
+                        string.push(char);
+                        while let Some((_, next_char)) = file_content_chars.peek() {
+                            if next_char.is_numeric() || *next_char == '.' {
+                                string.push(*next_char);
+                                file_content_chars.next();
+                            } else {
+                                break;
+                            }
+                        }
+                        tokens.push(Token::new(
+                            TokenType::Number(string.parse().unwrap()),
+                            string,
+                            line_number,
+                            column,
+                            start,
+                        ));
+                    }
+                    char if char.is_alphabetic() || char == '_' => {
+                        let mut string = String::from(char);
+                        while let Some((_, next_char)) = file_content_chars.peek() {
+                            if next_char.is_alphanumeric() || *next_char == '_' {
+                                string.push(*next_char);
+                                file_content_chars.next();
+                            } else {
+                                break;
+                            }
+                        }
+                        let token_type = check_reserved(&string);
+                        tokens.push(Token::new(token_type, string, line_number, column, start));
+                    }
+                    '\n' => {
+                        line_number += 1;
+                        columns.new_line(start + 1);
+                    }
+                    '\t' | '\r' | ' ' => {}
+                    _ => {
+                        errors.push(ScanError::UnexpectedCharacter {
+                            line: line_number,
+                            column,
+                            span: Span::new(start, start + char.len_utf8()),
+                            character: char,
+                        });
+                    }
+                }
+            }
+        }
+
+        let column = columns.at(&file_contents, file_contents.len());
+        tokens.push(Token::new(
+            TokenType::Eof,
+            "".to_string(),
+            line_number,
+            column,
+            file_contents.len(),
+        ));
+
+        return (tokens, comments, errors);
+    }
+}
+
+impl ScanError {
+    pub fn location(&self) -> (usize, usize, Span) {
+        match self {
+            ScanError::UnexpectedCharacter {
+                line, column, span, ..
+            }
+            | ScanError::UnterminatedString { line, column, span } => (*line, *column, *span),
+        }
+    }
+
+    /// What went wrong, without the line the full message starts with.
+    pub fn message(&self) -> String {
+        match self {
+            ScanError::UnexpectedCharacter { character, .. } => {
+                format!("Unexpected character: {}", character)
+            }
+            ScanError::UnterminatedString { .. } => "Unterminated string.".to_string(),
+        }
+    }
+}
+
+/// The 1-based column of each token, counted on from the previous token
+/// rather than from the start of the line, so long lines scan in linear time.
+struct Columns {
+    offset: usize,
+    column: usize,
+}
+
+impl Columns {
+    fn new_line(&mut self, line_start: usize) {
+        self.offset = line_start;
+        self.column = 1;
+    }
+
+    /// The column of the character at byte `index`, which can't come before
+    /// the one last asked about.
+    fn at(&mut self, source: &str, index: usize) -> usize {
+        self.column += source[self.offset..index].chars().count();
+        self.offset = index;
+        return self.column;
+    }
+}
+
+/// Splits the text between two tokens into trivia.
+fn trivia(text: &str) -> Vec<Trivia> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (kind, len) = if rest.starts_with("\r\n") {
+            (TriviaKind::Newline, 2)
+        } else if rest.starts_with('\n') {
+            (TriviaKind::Newline, 1)
+        } else if rest.starts_with("//") {
+            let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+            (TriviaKind::Comment, line.trim_end_matches('\r').len())
+        } else if is_whitespace(rest) {
+            let mut len = 0;
+            while is_whitespace(&rest[len..]) {
+                len += 1;
+            }
+            (TriviaKind::Whitespace, len)
+        } else {
+            let mut len = 0;
+            while len < rest.len()
+                && !rest[len..].starts_with('\n')
+                && !rest[len..].starts_with("//")
+                && !is_whitespace(&rest[len..])
+            {
+                len += rest[len..].chars().next().unwrap().len_utf8();
+            }
+            (TriviaKind::Skipped, len)
+        };
+        pieces.push(Trivia {
+            kind,
+            text: rest[..len].to_string(),
+        });
+        rest = &rest[len..];
+    }
+    return pieces;
+}
+
+/// Whether `text` starts with whitespace that doesn't end a line.
+fn is_whitespace(text: &str) -> bool {
+    return text.starts_with([' ', '\t']) || (text.starts_with('\r') && !text.starts_with("\r\n"));
+}
@@ -0,0 +1,334 @@
+use std::mem;
+
+use crate::intern::Symbol;
+
+/// A hash table keyed by interned strings, using open addressing with linear
+/// probing. Lookups reuse the symbol's precomputed hash and compare keys by
+/// pointer, so they never touch the key's text.
+#[derive(Clone)]
+pub struct Table<V> {
+    entries: Vec<Entry<V>>,
+    /// Live entries.
+    len: usize,
+    /// Live entries plus tombstones, which both count towards the load.
+    used: usize,
+}
+
+#[derive(Clone)]
+enum Entry<V> {
+    Empty,
+    /// A removed entry. Probing continues past it so keys inserted after a
+    /// collision stay reachable.
+    Tombstone,
+    Occupied(Symbol, V),
+}
+
+impl<V> Table<V> {
+    pub fn new() -> Self {
+        Table {
+            entries: Vec::new(),
+            len: 0,
+            used: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    pub fn get(&self, key: &Symbol) -> Option<&V> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        match &self.entries[self.find(key)] {
+            Entry::Occupied(_, value) => return Some(value),
+            _ => return None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &Symbol) -> Option<&mut V> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let index = self.find(key);
+        match &mut self.entries[index] {
+            Entry::Occupied(_, value) => return Some(value),
+            _ => return None,
+        }
+    }
+
+    pub fn contains_key(&self, key: &Symbol) -> bool {
+        return self.get(key).is_some();
+    }
+
+    /// Inserts `value` under `key`, returning the value it replaced.
+    pub fn insert(&mut self, key: Symbol, value: V) -> Option<V> {
+        if (self.used + 1) * 4 > self.entries.len() * 3 {
+            self.rehash();
+        }
+
+        let index = self.find(&key);
+        match mem::replace(&mut self.entries[index], Entry::Occupied(key, value)) {
+            Entry::Occupied(_, previous) => return Some(previous),
+            Entry::Tombstone => {
+                self.len += 1;
+                return None;
+            }
+            Entry::Empty => {
+                self.len += 1;
+                self.used += 1;
+                return None;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Symbol) -> Option<V> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let index = self.find(key);
+        if !matches!(self.entries[index], Entry::Occupied(..)) {
+            return None;
+        }
+        let Entry::Occupied(_, value) = mem::replace(&mut self.entries[index], Entry::Tombstone)
+        else {
+            unreachable!();
+        };
+        self.len -= 1;
+        return Some(value);
+    }
+
+    /// Removes every entry for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Symbol, &V) -> bool) {
+        for entry in &mut self.entries {
+            if let Entry::Occupied(key, value) = entry {
+                if !keep(key, value) {
+                    *entry = Entry::Tombstone;
+                    self.len -= 1;
+                }
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Symbol, &V)> {
+        return self.entries.iter().filter_map(|entry| match entry {
+            Entry::Occupied(key, value) => Some((key, value)),
+            _ => None,
+        });
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        return self.iter().map(|(_, value)| value);
+    }
+
+    /// The slot holding `key`, or else the slot it should be inserted into:
+    /// the first tombstone on its probe sequence, or the empty slot ending
+    /// it. The table must have at least one empty slot.
+    fn find(&self, key: &Symbol) -> usize {
+        let mask = self.entries.len() - 1;
+        let mut index = key.hash_code() as usize & mask;
+        let mut tombstone = None;
+        loop {
+            match &self.entries[index] {
+                Entry::Empty => return tombstone.unwrap_or(index),
+                Entry::Tombstone => {
+                    tombstone.get_or_insert(index);
+                }
+                Entry::Occupied(existing, _) if existing == key => return index,
+                Entry::Occupied(..) => {}
+            }
+            index = (index + 1) & mask;
+        }
+    }
+
+    /// Reinserts the live entries, dropping tombstones. The capacity doubles
+    /// unless the live entries fill less than half of it, so a table that
+    /// churns through keys doesn't keep growing.
+    fn rehash(&mut self) {
+        let capacity = if self.len * 2 >= self.entries.len() {
+            (self.entries.len() * 2).max(8)
+        } else {
+            self.entries.len()
+        };
+        let entries = mem::replace(
+            &mut self.entries,
+            (0..capacity).map(|_| Entry::Empty).collect(),
+        );
+        self.len = 0;
+        self.used = 0;
+        for entry in entries {
+            if let Entry::Occupied(key, value) = entry {
+                let index = self.find(&key);
+                self.entries[index] = Entry::Occupied(key, value);
+                self.len += 1;
+                self.used += 1;
+            }
+        }
+    }
+}
+
+impl<V> Default for Table<V> {
+    fn default() -> Self {
+        Table::new()
+    }
+}
+
+impl<V> Extend<(Symbol, V)> for Table<V> {
+    fn extend<I: IntoIterator<Item = (Symbol, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<V> IntoIterator for Table<V> {
+    type Item = (Symbol, V);
+    type IntoIter = IntoIter<V>;
+
+    fn into_iter(self) -> IntoIter<V> {
+        return IntoIter(self.entries.into_iter());
+    }
+}
+
+/// The entries of a [`Table`], moved out of it in slot order.
+pub struct IntoIter<V>(std::vec::IntoIter<Entry<V>>);
+
+impl<V> Iterator for IntoIter<V> {
+    type Item = (Symbol, V);
+
+    fn next(&mut self) -> Option<(Symbol, V)> {
+        for entry in self.0.by_ref() {
+            if let Entry::Occupied(key, value) = entry {
+                return Some((key, value));
+            }
+        }
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `count` distinct keys whose hashes agree in the low byte, so they
+    /// share a probe sequence in any table of up to 256 slots.
+    fn colliding_keys(count: usize) -> Vec<Symbol> {
+        let first = Symbol::new("key0");
+        let mut keys = vec![first.clone()];
+        for n in 1.. {
+            if keys.len() == count {
+                break;
+            }
+            let key = Symbol::new(&format!("key{}", n));
+            if key.hash_code() & 0xff == first.hash_code() & 0xff {
+                keys.push(key);
+            }
+        }
+        return keys;
+    }
+
+    #[test]
+    fn colliding_keys_stay_reachable_through_tombstones() {
+        let keys = colliding_keys(3);
+        let mut table = Table::new();
+        for (n, key) in keys.iter().enumerate() {
+            assert_eq!(table.insert(key.clone(), n), None);
+        }
+
+        assert_eq!(table.remove(&keys[0]), Some(0));
+        assert_eq!(table.remove(&keys[0]), None);
+        assert_eq!(table.get(&keys[1]), Some(&1));
+        assert_eq!(table.get(&keys[2]), Some(&2));
+
+        // The key already sits past the tombstone, so it's replaced there
+        // rather than inserted a second time.
+        assert_eq!(table.insert(keys[2].clone(), 20), Some(2));
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.remove(&keys[2]), Some(20));
+        assert_eq!(table.get(&keys[2]), None);
+
+        assert_eq!(table.insert(keys[0].clone(), 10), None);
+        assert_eq!(table.insert(keys[2].clone(), 30), None);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.used, 3);
+        assert_eq!(table.get(&keys[0]), Some(&10));
+        assert_eq!(table.get(&keys[1]), Some(&1));
+        assert_eq!(table.get(&keys[2]), Some(&30));
+    }
+
+    #[test]
+    fn insert_after_retain() {
+        let keys = colliding_keys(4);
+        let mut table = Table::new();
+        for (n, key) in keys.iter().enumerate() {
+            table.insert(key.clone(), n);
+        }
+
+        table.retain(|_, &value| value % 2 == 1);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(&keys[0]), None);
+        assert_eq!(table.get(&keys[3]), Some(&3));
+
+        assert_eq!(table.insert(keys[3].clone(), 30), Some(3));
+        assert_eq!(table.insert(keys[2].clone(), 20), None);
+        assert_eq!(table.len(), 3);
+        let mut entries: Vec<_> = table
+            .iter()
+            .map(|(key, &value)| (key.clone(), value))
+            .collect();
+        entries.sort_by_key(|&(_, value)| value);
+        assert_eq!(
+            entries,
+            vec![
+                (keys[1].clone(), 1),
+                (keys[2].clone(), 20),
+                (keys[3].clone(), 30)
+            ]
+        );
+    }
+
+    #[test]
+    fn growing_drops_tombstones() {
+        let keys: Vec<Symbol> = (0..100)
+            .map(|n| Symbol::new(&format!("key{}", n)))
+            .collect();
+        let mut table = Table::new();
+        for (n, key) in keys.iter().enumerate().take(50) {
+            table.insert(key.clone(), n);
+        }
+        for key in keys.iter().step_by(2).take(25) {
+            table.remove(key);
+        }
+        for (n, key) in keys.iter().enumerate().skip(50) {
+            table.insert(key.clone(), n);
+        }
+
+        assert_eq!(table.len(), 75);
+        for (n, key) in keys.iter().enumerate() {
+            let expected = if n < 50 && n % 2 == 0 { None } else { Some(&n) };
+            assert_eq!(table.get(key), expected, "{}", key);
+        }
+        let tombstones = table
+            .entries
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Tombstone))
+            .count();
+        assert_eq!(table.used, table.len() + tombstones);
+    }
+
+    #[test]
+    fn tombstones_count_towards_the_load() {
+        let mut table = Table::new();
+        for n in 0..1000 {
+            let key = Symbol::new(&format!("key{}", n));
+            table.insert(key.clone(), n);
+            assert_eq!(table.remove(&key), Some(n));
+        }
+        assert!(table.is_empty());
+        assert!(table.entries.len() <= 16);
+    }
+}
@@ -0,0 +1,784 @@
+mod chunk;
+mod compiler;
+mod debug;
+mod heap;
+mod value;
+
+use std::rc::Rc;
+
+use self::chunk::{Chunk, Location, OpCode};
+use self::heap::{Heap, ObjRef};
+use self::value::{BoundMethod, Class, Closure, Instance, Native, Object, Printer, Upvalue, Value};
+use crate::intern::Symbol;
+use crate::interpreter::{self, clock, Interpreter, RuntimeError, MAX_CALL_DEPTH};
+use crate::optimizer;
+use crate::resolver::Resolver;
+use crate::scanner::{Token, TokenType};
+use crate::table::Table;
+use crate::LoxError;
+
+struct CallFrame {
+    closure: ObjRef,
+    /// The closure's code, held directly so instruction fetch skips the heap.
+    chunk: Rc<Chunk>,
+    ip: usize,
+    /// Index of the frame's slot zero in the value stack.
+    slots: usize,
+}
+
+/// A stack-based virtual machine executing bytecode compiled from the
+/// resolved AST. Produces the same output and errors as [`Interpreter`].
+pub struct Vm {
+    heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: Table<Value>,
+    /// Upvalues still pointing into the stack, so closures created in the
+    /// same scope share them.
+    open_upvalues: Vec<ObjRef>,
+    /// Looked up on every class call, so it's interned once up front.
+    init_string: Symbol,
+    optimize: bool,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        let mut vm = Vm {
+            heap: Heap::new(),
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: Table::new(),
+            open_upvalues: Vec::new(),
+            init_string: Symbol::new("init"),
+            optimize: true,
+        };
+        vm.define_native("clock", 0, clock);
+        return vm;
+    }
+
+    /// Runs a full collection before every allocation instead of waiting for
+    /// the heap to grow, to flush out objects the VM forgot to root.
+    pub fn set_stress_gc(&mut self, stress: bool) {
+        self.heap.set_stress(stress);
+    }
+
+    /// Whether [`Vm::run`] folds constants and drops dead branches before
+    /// compiling. On by default.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Exposes a host function to scripts as a global called `name`, like
+    /// [`Interpreter::define_native`]. Only nil, booleans, numbers and
+    /// strings cross between the VM and the host; passing or returning any
+    /// other value is a runtime error at the call. This differs from the
+    /// tree-walker, which hands natives functions, classes and instances
+    /// too, so a script passing those to a native only runs there.
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&[interpreter::Object]) -> Result<interpreter::Object, String> + 'static,
+    {
+        let native = Native {
+            arity,
+            function: Rc::new(function),
+        };
+        let native = self.alloc(Object::Native(native));
+        self.globals
+            .insert(Symbol::new(name), Value::object(native));
+    }
+
+    /// Scans, parses, resolves, compiles and executes a whole program.
+    pub fn run(&mut self, source: &str) -> Result<(), LoxError> {
+        let function = compile(source, &mut self.heap, self.optimize)?;
+        self.interpret(function)?;
+        return Ok(());
+    }
+
+    fn interpret(&mut self, function: ObjRef) -> Result<(), RuntimeError> {
+        // The function isn't rooted until the closure is on the stack, so
+        // allocate the closure without giving the collector a chance to run.
+        let closure = self.heap.alloc(Object::Closure(Closure {
+            function,
+            upvalues: Vec::new(),
+        }));
+        self.stack.push(Value::object(closure));
+        self.frames.push(CallFrame {
+            closure,
+            chunk: Rc::clone(&self.heap.function(function).chunk),
+            ip: 0,
+            slots: 0,
+        });
+
+        let result = self.execute();
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        return result;
+    }
+
+    fn execute(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            let instruction = self.read_byte();
+            let Ok(op) = OpCode::try_from(instruction) else {
+                unreachable!("the compiler only emits valid opcodes");
+            };
+
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.stack.push(constant);
+                }
+                OpCode::Nil => self.stack.push(Value::NIL),
+                OpCode::True => self.stack.push(Value::boolean(true)),
+                OpCode::False => self.stack.push(Value::boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack.push(self.stack[slot]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(self.heap.string(name)) {
+                        Some(value) => self.stack.push(*value),
+                        None => return Err(self.undefined_variable(name)),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let value = self.pop();
+                    self.globals.insert(self.heap.string(name).clone(), value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0);
+                    match self.globals.get_mut(self.heap.string(name)) {
+                        Some(global) => *global = value,
+                        None => return Err(self.undefined_variable(name)),
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.heap.closure(self.frame().closure).upvalues[index];
+                    let value = match self.heap.upvalue(upvalue) {
+                        Upvalue::Open(slot) => self.stack[*slot],
+                        Upvalue::Closed(value) => *value,
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = self.heap.closure(self.frame().closure).upvalues[index];
+                    let value = self.peek(0);
+                    match self.heap.upvalue_mut(upvalue) {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let Some(instance) = self.instance(self.peek(0)) else {
+                        let token = self.token(self.heap.string(name));
+                        return Err(RuntimeError::OnlyInstancesHaveProperties(token));
+                    };
+
+                    let field = instance.fields.get(self.heap.string(name)).copied();
+                    if let Some(value) = field {
+                        self.pop();
+                        self.stack.push(value);
+                        continue;
+                    }
+
+                    let class = instance.class;
+                    self.bind_method(class, name)?;
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let (Some(instance), Some(_)) =
+                        (self.peek(1).as_object(), self.instance(self.peek(1)))
+                    else {
+                        let token = self.token(self.heap.string(name));
+                        return Err(RuntimeError::OnlyInstancesHaveFields(token));
+                    };
+
+                    let value = self.pop();
+                    let name = self.heap.string(name).clone();
+                    self.heap.instance_mut(instance).fields.insert(name, value);
+                    self.pop();
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    let Some(superclass) = self.pop().as_object() else {
+                        unreachable!("'super' always holds a class");
+                    };
+                    self.bind_method(superclass, name)?;
+                }
+                OpCode::Equal => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    self.stack.push(Value::boolean(lhs == rhs));
+                }
+                OpCode::Greater => {
+                    let (lhs, rhs) = self.number_operands()?;
+                    self.stack.push(Value::boolean(lhs > rhs));
+                }
+                OpCode::GreaterEqual => {
+                    let (lhs, rhs) = self.number_operands()?;
+                    self.stack.push(Value::boolean(lhs >= rhs));
+                }
+                OpCode::Less => {
+                    let (lhs, rhs) = self.number_operands()?;
+                    self.stack.push(Value::boolean(lhs < rhs));
+                }
+                OpCode::LessEqual => {
+                    let (lhs, rhs) = self.number_operands()?;
+                    self.stack.push(Value::boolean(lhs <= rhs));
+                }
+                OpCode::Add => {
+                    let (lhs, rhs) = (self.peek(1), self.peek(0));
+                    let value = if let (Some(n), Some(m)) = (lhs.as_number(), rhs.as_number()) {
+                        Value::number(n + m)
+                    } else if let (Some(s), Some(t)) = (self.string(lhs), self.string(rhs)) {
+                        let concatenated = format!("{}{}", s, t);
+                        // Both operands stay on the stack until the result
+                        // is allocated, so a collection here keeps them.
+                        Value::object(self.intern(&concatenated))
+                    } else {
+                        let location = self.location();
+                        return Err(RuntimeError::OperandsMustBeNumbersOrStrings(
+                            self.token(""),
+                            location.span,
+                        ));
+                    };
+                    self.pop();
+                    self.pop();
+                    self.stack.push(value);
+                }
+                OpCode::Subtract => {
+                    let (lhs, rhs) = self.number_operands()?;
+                    self.stack.push(Value::number(lhs - rhs));
+                }
+                OpCode::Multiply => {
+                    let (lhs, rhs) = self.number_operands()?;
+                    self.stack.push(Value::number(lhs * rhs));
+                }
+                OpCode::Divide => {
+                    let (lhs, rhs) = self.number_operands()?;
+                    self.stack.push(Value::number(lhs / rhs));
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Value::boolean(value.is_falsey()));
+                }
+                OpCode::Negate => {
+                    let Some(n) = self.peek(0).as_number() else {
+                        let location = self.location();
+                        return Err(RuntimeError::OperandMustBeNumber(
+                            self.token(""),
+                            location.span,
+                        ));
+                    };
+                    self.pop();
+                    self.stack.push(Value::number(-n));
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    let heap = &self.heap;
+                    println!("{}", Printer { heap, value });
+                }
+                OpCode::Jump => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_short();
+                    if self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let count = self.read_byte() as usize;
+                    self.call_value(count)?;
+                }
+                OpCode::Closure => {
+                    let Some(function) = self.read_constant().as_object() else {
+                        unreachable!("closures are only built from function constants");
+                    };
+                    let count = self.heap.function(function).upvalue_count;
+                    let mut upvalues = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        if is_local {
+                            let slot = self.frame().slots + index;
+                            upvalues.push(self.capture_upvalue(slot));
+                        } else {
+                            let enclosing = self.heap.closure(self.frame().closure);
+                            upvalues.push(enclosing.upvalues[index]);
+                        }
+                    }
+                    let closure = self.alloc(Object::Closure(Closure { function, upvalues }));
+                    self.stack.push(Value::object(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    let class = Class {
+                        name: self.heap.string(name).clone(),
+                        methods: Table::new(),
+                    };
+                    let class = self.alloc(Object::Class(class));
+                    self.stack.push(Value::object(class));
+                }
+                OpCode::Inherit => {
+                    let Some(superclass) = self.class(self.peek(1)) else {
+                        return Err(RuntimeError::SuperclassMustBeClass(self.token("")));
+                    };
+                    let Some(subclass) = self.peek(0).as_object() else {
+                        unreachable!("'Inherit' always follows the subclass");
+                    };
+                    // Copy the methods down now: a subclass can't change its
+                    // superclass later, and overrides are added afterwards.
+                    let methods = superclass.methods.clone();
+                    self.heap.class_mut(subclass).methods.extend(methods);
+                    self.pop();
+                }
+                OpCode::Method => {
+                    let name = self.read_string();
+                    let Some(method) = self.peek(0).as_object() else {
+                        unreachable!("methods are always closures");
+                    };
+                    let Some(class) = self.peek(1).as_object() else {
+                        unreachable!("methods are always defined on the class below them");
+                    };
+                    let name = self.heap.string(name).clone();
+                    self.heap.class_mut(class).methods.insert(name, method);
+                    self.pop();
+                }
+            }
+        }
+    }
+
+    fn call_value(&mut self, count: usize) -> Result<(), RuntimeError> {
+        let callee = self.stack.len() - count - 1;
+        let Some(object) = self.stack[callee].as_object() else {
+            let location = self.location();
+            return Err(RuntimeError::NotCallable(self.token(")"), location.span));
+        };
+
+        match self.heap.get(object) {
+            Object::Closure(_) => return self.call(object, count),
+            Object::Native(native) => {
+                let (arity, function) = (native.arity, Rc::clone(&native.function));
+                self.check_arity(arity, count)?;
+                let arguments: Option<Vec<_>> = self.stack[callee + 1..]
+                    .iter()
+                    .map(|&argument| self.host_object(argument))
+                    .collect();
+                let Some(arguments) = arguments else {
+                    let message = "Native arguments must be nil, booleans, numbers or strings.";
+                    return Err(self.native_error(message.to_string()));
+                };
+                let result = function(&arguments).map_err(|message| self.native_error(message))?;
+                // The arguments stay on the stack while a string result is
+                // interned, so they can't be collected before the call ends.
+                let Some(result) = self.value_from_host(result) else {
+                    let message = "Native results must be nil, booleans, numbers or strings.";
+                    return Err(self.native_error(message.to_string()));
+                };
+                self.stack.truncate(callee);
+                self.stack.push(result);
+                return Ok(());
+            }
+            Object::Class(class) => {
+                let initializer = class.methods.get(&self.init_string).copied();
+                let instance = Instance {
+                    class: object,
+                    fields: Table::new(),
+                };
+                // The class stays rooted in the callee slot until the
+                // instance replaces it.
+                let instance = self.alloc(Object::Instance(instance));
+                self.stack[callee] = Value::object(instance);
+
+                match initializer {
+                    Some(initializer) => return self.call(initializer, count),
+                    None => return self.check_arity(0, count),
+                }
+            }
+            Object::BoundMethod(bound) => {
+                let method = bound.method;
+                self.stack[callee] = bound.receiver;
+                return self.call(method, count);
+            }
+            _ => {
+                let location = self.location();
+                return Err(RuntimeError::NotCallable(self.token(")"), location.span));
+            }
+        }
+    }
+
+    fn call(&mut self, closure: ObjRef, count: usize) -> Result<(), RuntimeError> {
+        let function = self.heap.function(self.heap.closure(closure).function);
+        self.check_arity(function.arity, count)?;
+        if self.frames.len() == MAX_CALL_DEPTH {
+            let location = self.location();
+            return Err(RuntimeError::StackOverflow(self.token(")"), location.span));
+        }
+        let chunk = Rc::clone(&function.chunk);
+        self.frames.push(CallFrame {
+            closure,
+            chunk,
+            ip: 0,
+            slots: self.stack.len() - count - 1,
+        });
+        return Ok(());
+    }
+
+    fn check_arity(&self, expected: usize, got: usize) -> Result<(), RuntimeError> {
+        if expected != got {
+            return Err(RuntimeError::ArityMismatch {
+                paren: self.token(")"),
+                span: self.location().span,
+                expected,
+                got,
+            });
+        }
+        return Ok(());
+    }
+
+    /// Replaces the receiver on top of the stack with its class's method
+    /// `name` bound to it.
+    fn bind_method(&mut self, class: ObjRef, name: ObjRef) -> Result<(), RuntimeError> {
+        let name = self.heap.string(name);
+        let Some(&method) = self.heap.class(class).methods.get(name) else {
+            return Err(RuntimeError::UndefinedProperty(self.token(name)));
+        };
+        let receiver = self.peek(0);
+        let bound = self.alloc(Object::BoundMethod(BoundMethod { receiver, method }));
+        self.pop();
+        self.stack.push(Value::object(bound));
+        return Ok(());
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let heap = &self.heap;
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|&&upvalue| matches!(heap.upvalue(upvalue), Upvalue::Open(s) if *s == slot));
+        if let Some(&upvalue) = existing {
+            return upvalue;
+        }
+        let upvalue = self.alloc(Object::Upvalue(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue);
+        return upvalue;
+    }
+
+    /// Moves every captured variable at or above `last` off the stack and
+    /// into its upvalue.
+    fn close_upvalues(&mut self, last: usize) {
+        let heap = &mut self.heap;
+        let stack = &self.stack;
+        self.open_upvalues.retain(|&reference| {
+            let upvalue = heap.upvalue_mut(reference);
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= last => {
+                    *upvalue = Upvalue::Closed(stack[slot]);
+                    return false;
+                }
+                _ => return true,
+            }
+        });
+    }
+
+    /// Allocates `object`, first collecting garbage if the heap has grown
+    /// enough. Anything the caller still needs must be reachable from a root.
+    fn alloc(&mut self, object: Object) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        return self.heap.alloc(object);
+    }
+
+    fn intern(&mut self, string: &str) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        return self.heap.intern(string);
+    }
+
+    fn collect_garbage(&mut self) {
+        for value in &self.stack {
+            self.heap.mark_value(*value);
+        }
+        for frame in &self.frames {
+            self.heap.mark_object(frame.closure);
+        }
+        for upvalue in &self.open_upvalues {
+            self.heap.mark_object(*upvalue);
+        }
+        for value in self.globals.values() {
+            self.heap.mark_value(*value);
+        }
+        self.heap.collect();
+    }
+
+    fn string(&self, value: Value) -> Option<&Symbol> {
+        match self.heap.get(value.as_object()?) {
+            Object::String(string) => return Some(string),
+            _ => return None,
+        }
+    }
+
+    fn instance(&self, value: Value) -> Option<&Instance> {
+        match self.heap.get(value.as_object()?) {
+            Object::Instance(instance) => return Some(instance),
+            _ => return None,
+        }
+    }
+
+    fn class(&self, value: Value) -> Option<&Class> {
+        match self.heap.get(value.as_object()?) {
+            Object::Class(class) => return Some(class),
+            _ => return None,
+        }
+    }
+
+    fn number_operands(&mut self) -> Result<(f64, f64), RuntimeError> {
+        let (Some(lhs), Some(rhs)) = (self.peek(1).as_number(), self.peek(0).as_number()) else {
+            let location = self.location();
+            return Err(RuntimeError::OperandsMustBeNumbers(
+                self.token(""),
+                location.span,
+            ));
+        };
+        self.pop();
+        self.pop();
+        return Ok((lhs, rhs));
+    }
+
+    fn undefined_variable(&self, name: ObjRef) -> RuntimeError {
+        return RuntimeError::UndefinedVariable(self.token(self.heap.string(name)));
+    }
+
+    fn frame(&self) -> &CallFrame {
+        return self.frames.last().unwrap();
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        return self.frames.last_mut().unwrap();
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.chunk.code[frame.ip];
+        frame.ip += 1;
+        return byte;
+    }
+
+    fn read_short(&mut self) -> usize {
+        let high = self.read_byte() as usize;
+        let low = self.read_byte() as usize;
+        return (high << 8) | low;
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_byte() as usize;
+        return self.frame().chunk.constants[index];
+    }
+
+    fn read_string(&mut self) -> ObjRef {
+        let Some(name) = self.read_constant().as_object() else {
+            unreachable!("names are always string constants");
+        };
+        return name;
+    }
+
+    fn pop(&mut self) -> Value {
+        return self.stack.pop().unwrap();
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        return self.stack[self.stack.len() - 1 - distance];
+    }
+
+    /// Where the instruction being executed came from.
+    fn location(&self) -> Location {
+        let frame = self.frame();
+        return frame.chunk.locations[frame.ip - 1];
+    }
+
+    /// `value` as a native sees it, or `None` for values the host can't
+    /// represent.
+    fn host_object(&self, value: Value) -> Option<interpreter::Object> {
+        if let Some(n) = value.as_number() {
+            return Some(interpreter::Object::Number(n));
+        }
+        if let Some(b) = value.as_boolean() {
+            return Some(interpreter::Object::Boolean(b));
+        }
+        let Some(object) = value.as_object() else {
+            return Some(interpreter::Object::Nil);
+        };
+        match self.heap.get(object) {
+            Object::String(string) => return Some(interpreter::Object::String(string.clone())),
+            _ => return None,
+        }
+    }
+
+    /// The VM value for what a native returned, or `None` if the VM can't
+    /// represent it.
+    fn value_from_host(&mut self, object: interpreter::Object) -> Option<Value> {
+        match object {
+            interpreter::Object::Nil => return Some(Value::NIL),
+            interpreter::Object::Boolean(b) => return Some(Value::boolean(b)),
+            interpreter::Object::Number(n) => return Some(Value::number(n)),
+            interpreter::Object::String(string) => {
+                return Some(Value::object(self.intern(&string)));
+            }
+            _ => return None,
+        }
+    }
+
+    fn native_error(&self, message: String) -> RuntimeError {
+        return RuntimeError::Native {
+            paren: self.token(")"),
+            message,
+        };
+    }
+
+    /// Rebuilds the token the current instruction was compiled from, so
+    /// runtime errors carry the same position as the tree-walker's.
+    fn token(&self, lexeme: &str) -> Token {
+        let location = self.location();
+        let mut token = Token::new(
+            TokenType::Identifier,
+            lexeme.to_string(),
+            location.line,
+            location.column,
+            location.token.start,
+        );
+        token.span = location.token;
+        return token;
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Vm::new()
+    }
+}
+
+fn compile(source: &str, heap: &mut Heap, optimize: bool) -> Result<ObjRef, LoxError> {
+    let mut statements = crate::parse_program(source)?;
+    Resolver::new(&mut Interpreter::new())
+        .resolve(&statements)
+        .map_err(LoxError::Compile)?;
+    if optimize {
+        statements = optimizer::optimize(statements);
+    }
+    return compiler::compile(&statements, heap).map_err(LoxError::Compile);
+}
+
+/// Compiles a whole program and lists the bytecode of the script and of every
+/// function in it, optimizing first if `optimize` is set.
+pub fn disassemble(source: &str, optimize: bool) -> Result<String, LoxError> {
+    let mut heap = Heap::new();
+    let function = compile(source, &mut heap, optimize)?;
+    return Ok(debug::disassemble_function(&heap, function));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[test]
+    fn natives_defined_by_the_host_are_callable() {
+        let recorded = Rc::new(RefCell::new(Vec::new()));
+        let mut vm = Vm::new();
+        vm.set_stress_gc(true);
+        let sink = Rc::clone(&recorded);
+        vm.define_native("record", 1, move |arguments| {
+            sink.borrow_mut().push(arguments[0].to_string());
+            return Ok(interpreter::Object::Nil);
+        });
+        vm.define_native("greet", 1, |arguments| {
+            let name = String::try_from(&arguments[0])?;
+            return Ok(format!("hello {}", name).into());
+        });
+
+        vm.run("record(greet(\"lox\")); record(1.5); record(true); record(nil);")
+            .unwrap();
+        assert_eq!(*recorded.borrow(), ["hello lox", "1.5", "true", "nil"]);
+        assert!(vm.run("print clock() > 0;").is_ok());
+    }
+
+    #[test]
+    fn natives_only_take_primitives_unlike_on_the_tree_walker() {
+        let type_name = |arguments: &[interpreter::Object]| {
+            return Ok(arguments[0].type_name().into());
+        };
+        let source = "fun f() {}\nprint typeName(f);";
+
+        let mut tree = Interpreter::new();
+        tree.define_native("typeName", 1, type_name);
+        assert!(tree.run(source).is_ok());
+
+        let mut vm = Vm::new();
+        vm.define_native("typeName", 1, type_name);
+        match vm.run(source) {
+            Err(LoxError::Runtime(error)) => assert_eq!(
+                error.to_string(),
+                "Native arguments must be nil, booleans, numbers or strings.\n[line 2]"
+            ),
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn natives_report_errors_at_the_call() {
+        let mut vm = Vm::new();
+        vm.define_native("greet", 1, |arguments| {
+            let name = String::try_from(&arguments[0])?;
+            return Ok(name.into());
+        });
+
+        match vm.run("print 1;\ngreet(2);") {
+            Err(LoxError::Runtime(error)) => {
+                assert_eq!(
+                    error.to_string(),
+                    "Expected a string but got number.\n[line 2]"
+                );
+            }
+            _ => panic!("expected a runtime error"),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+
+use super::value::Value;
+use crate::scanner::{Span, Token};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    Inherit,
+    Method,
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        let op = match byte {
+            0 => OpCode::Constant,
+            1 => OpCode::Nil,
+            2 => OpCode::True,
+            3 => OpCode::False,
+            4 => OpCode::Pop,
+            5 => OpCode::GetLocal,
+            6 => OpCode::SetLocal,
+            7 => OpCode::GetGlobal,
+            8 => OpCode::DefineGlobal,
+            9 => OpCode::SetGlobal,
+            10 => OpCode::GetUpvalue,
+            11 => OpCode::SetUpvalue,
+            12 => OpCode::GetProperty,
+            13 => OpCode::SetProperty,
+            14 => OpCode::GetSuper,
+            15 => OpCode::Equal,
+            16 => OpCode::Greater,
+            17 => OpCode::GreaterEqual,
+            18 => OpCode::Less,
+            19 => OpCode::LessEqual,
+            20 => OpCode::Add,
+            21 => OpCode::Subtract,
+            22 => OpCode::Multiply,
+            23 => OpCode::Divide,
+            24 => OpCode::Not,
+            25 => OpCode::Negate,
+            26 => OpCode::Print,
+            27 => OpCode::Jump,
+            28 => OpCode::JumpIfFalse,
+            29 => OpCode::Loop,
+            30 => OpCode::Call,
+            31 => OpCode::Closure,
+            32 => OpCode::CloseUpvalue,
+            33 => OpCode::Return,
+            34 => OpCode::Class,
+            35 => OpCode::Inherit,
+            36 => OpCode::Method,
+            _ => return Err(byte),
+        };
+        return Ok(op);
+    }
+}
+
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OpCode::Constant => write!(f, "OP_CONSTANT"),
+            OpCode::Nil => write!(f, "OP_NIL"),
+            OpCode::True => write!(f, "OP_TRUE"),
+            OpCode::False => write!(f, "OP_FALSE"),
+            OpCode::Pop => write!(f, "OP_POP"),
+            OpCode::GetLocal => write!(f, "OP_GET_LOCAL"),
+            OpCode::SetLocal => write!(f, "OP_SET_LOCAL"),
+            OpCode::GetGlobal => write!(f, "OP_GET_GLOBAL"),
+            OpCode::DefineGlobal => write!(f, "OP_DEFINE_GLOBAL"),
+            OpCode::SetGlobal => write!(f, "OP_SET_GLOBAL"),
+            OpCode::GetUpvalue => write!(f, "OP_GET_UPVALUE"),
+            OpCode::SetUpvalue => write!(f, "OP_SET_UPVALUE"),
+            OpCode::GetProperty => write!(f, "OP_GET_PROPERTY"),
+            OpCode::SetProperty => write!(f, "OP_SET_PROPERTY"),
+            OpCode::GetSuper => write!(f, "OP_GET_SUPER"),
+            OpCode::Equal => write!(f, "OP_EQUAL"),
+            OpCode::Greater => write!(f, "OP_GREATER"),
+            OpCode::GreaterEqual => write!(f, "OP_GREATER_EQUAL"),
+            OpCode::Less => write!(f, "OP_LESS"),
+            OpCode::LessEqual => write!(f, "OP_LESS_EQUAL"),
+            OpCode::Add => write!(f, "OP_ADD"),
+            OpCode::Subtract => write!(f, "OP_SUBTRACT"),
+            OpCode::Multiply => write!(f, "OP_MULTIPLY"),
+            OpCode::Divide => write!(f, "OP_DIVIDE"),
+            OpCode::Not => write!(f, "OP_NOT"),
+            OpCode::Negate => write!(f, "OP_NEGATE"),
+            OpCode::Print => write!(f, "OP_PRINT"),
+            OpCode::Jump => write!(f, "OP_JUMP"),
+            OpCode::JumpIfFalse => write!(f, "OP_JUMP_IF_FALSE"),
+            OpCode::Loop => write!(f, "OP_LOOP"),
+            OpCode::Call => write!(f, "OP_CALL"),
+            OpCode::Closure => write!(f, "OP_CLOSURE"),
+            OpCode::CloseUpvalue => write!(f, "OP_CLOSE_UPVALUE"),
+            OpCode::Return => write!(f, "OP_RETURN"),
+            OpCode::Class => write!(f, "OP_CLASS"),
+            OpCode::Inherit => write!(f, "OP_INHERIT"),
+            OpCode::Method => write!(f, "OP_METHOD"),
+        }
+    }
+}
+
+/// Where an instruction came from: the token it was compiled for and the span
+/// of the whole expression, so runtime errors render the same snippet as the
+/// tree-walker.
+#[derive(Clone, Copy, Debug)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    pub token: Span,
+    pub span: Span,
+}
+
+impl Location {
+    pub fn new(token: &Token, span: Span) -> Self {
+        Location {
+            line: token.line,
+            column: token.column,
+            token: token.span,
+            span,
+        }
+    }
+}
+
+/// A function's compiled bytecode with its constant pool and a per-byte line
+/// table.
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    pub locations: Vec<Location>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, location: Location) {
+        self.code.push(byte);
+        self.locations.push(location);
+    }
+
+    /// Appends `value` to the constant pool and returns its index.
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        return self.constants.len() - 1;
+    }
+}
@@ -0,0 +1,574 @@
+use std::rc::Rc;
+
+use super::chunk::{Chunk, Location, OpCode};
+use super::heap::{Heap, ObjRef};
+use super::value::{Function, Object, Value};
+use crate::ast::{Expression, FunctionDeclaration, Statement};
+use crate::parser::{FunctionKind, ParseError};
+use crate::scanner::{Span, Token, TokenType};
+
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+
+struct Local {
+    name: String,
+    depth: usize,
+    is_captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct UpvalueIndex {
+    index: u8,
+    is_local: bool,
+}
+
+/// Per-function compilation state. Nested function declarations push a new
+/// one, so the stack of states mirrors the lexical nesting.
+struct FunctionState {
+    name: String,
+    arity: usize,
+    chunk: Chunk,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueIndex>,
+    scope_depth: usize,
+    /// Set by the function's first error, after which the rest are likely
+    /// knock-on effects and aren't reported.
+    panic_mode: bool,
+}
+
+impl FunctionState {
+    fn new(name: String, kind: FunctionKind) -> Self {
+        // Slot zero holds the callee, or the receiver inside methods.
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            _ => "",
+        };
+        FunctionState {
+            name,
+            arity: 0,
+            chunk: Chunk::default(),
+            kind,
+            locals: vec![Local {
+                name: receiver.to_string(),
+                depth: 0,
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            panic_mode: false,
+        }
+    }
+}
+
+/// Compiles a resolved program into the bytecode of its top-level script
+/// function. Scoping errors are the resolver's job; this only reports the
+/// limits imposed by the bytecode format.
+pub fn compile(statements: &[Statement], heap: &mut Heap) -> Result<ObjRef, Vec<ParseError>> {
+    let mut compiler = Compiler {
+        heap,
+        states: vec![FunctionState::new(String::new(), FunctionKind::None)],
+        token: Token::new(TokenType::Eof, String::new(), 1, 1, 0),
+        location: Location {
+            line: 1,
+            column: 1,
+            token: Span::new(0, 0),
+            span: Span::new(0, 0),
+        },
+        errors: Vec::new(),
+    };
+
+    for statement in statements {
+        compiler.statement(statement);
+    }
+    compiler.emit_return();
+
+    if !compiler.errors.is_empty() {
+        return Err(compiler.errors);
+    }
+    let state = compiler.states.pop().unwrap();
+    return Ok(compiler.finish(state));
+}
+
+struct Compiler<'a> {
+    /// Where constants are allocated. Nothing is collected while compiling,
+    /// since the constants aren't reachable from any root yet.
+    heap: &'a mut Heap,
+    states: Vec<FunctionState>,
+    /// The token instructions are currently being emitted for.
+    token: Token,
+    location: Location,
+    errors: Vec<ParseError>,
+}
+
+impl Compiler<'_> {
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression) => {
+                self.expression(expression);
+                self.emit_op(OpCode::Pop);
+            }
+            Statement::Print(expression) => {
+                self.expression(expression);
+                self.emit_op(OpCode::Print);
+            }
+            Statement::Var(name, initializer) => {
+                match initializer {
+                    Some(expression) => self.expression(expression),
+                    None => self.emit_op(OpCode::Nil),
+                }
+                self.at(name);
+                self.define_variable(name);
+            }
+            Statement::Block(statements) => {
+                self.begin_scope();
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.end_scope();
+            }
+            Statement::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then_branch);
+
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump);
+                self.emit_op(OpCode::Pop);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(else_jump);
+            }
+            Statement::While(condition, body) => {
+                let loop_start = self.chunk_len();
+                self.expression(condition);
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(body);
+                self.emit_loop(loop_start);
+
+                self.patch_jump(exit_jump);
+                self.emit_op(OpCode::Pop);
+            }
+            Statement::Function(declaration) => {
+                self.at(&declaration.name);
+                // A local function is in scope inside its own body, so it can
+                // recurse.
+                if self.state().scope_depth > 0 {
+                    self.add_local(&declaration.name.lexeme);
+                }
+                self.function(declaration, FunctionKind::Function);
+                if self.state().scope_depth == 0 {
+                    let name = self.identifier_constant(&declaration.name.lexeme);
+                    self.emit_bytes(OpCode::DefineGlobal, name);
+                }
+            }
+            Statement::Return(keyword, value) => {
+                self.at(keyword);
+                match value {
+                    Some(value) => {
+                        self.expression(value);
+                        self.emit_op(OpCode::Return);
+                    }
+                    None => self.emit_return(),
+                }
+            }
+            Statement::Class(name, superclass, methods) => {
+                self.at(name);
+                let name_constant = self.identifier_constant(&name.lexeme);
+                if self.state().scope_depth > 0 {
+                    self.add_local(&name.lexeme);
+                }
+                self.emit_bytes(OpCode::Class, name_constant);
+                if self.state().scope_depth == 0 {
+                    self.emit_bytes(OpCode::DefineGlobal, name_constant);
+                }
+
+                if let Some(superclass) = superclass {
+                    self.expression(superclass);
+                    self.begin_scope();
+                    self.add_local("super");
+                    self.named_variable(&name.lexeme, false);
+                    self.at_expression(superclass);
+                    self.emit_op(OpCode::Inherit);
+                }
+
+                self.at(name);
+                self.named_variable(&name.lexeme, false);
+                for method in methods {
+                    let kind = match method.name.lexeme.as_str() {
+                        "init" => FunctionKind::Initializer,
+                        _ => FunctionKind::Method,
+                    };
+                    self.function(method, kind);
+                    let method_name = self.identifier_constant(&method.name.lexeme);
+                    self.emit_bytes(OpCode::Method, method_name);
+                }
+                self.emit_op(OpCode::Pop);
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, declaration: &FunctionDeclaration, kind: FunctionKind) {
+        self.states.push(FunctionState::new(
+            declaration.name.lexeme.to_string(),
+            kind,
+        ));
+        self.begin_scope();
+
+        self.state_mut().arity = declaration.params.len();
+        for param in &declaration.params {
+            self.at(param);
+            self.add_local(&param.lexeme);
+        }
+        for statement in &declaration.body {
+            self.statement(statement);
+        }
+        self.emit_return();
+
+        let state = self.states.pop().unwrap();
+        let upvalues = state.upvalues.clone();
+        let function = self.finish(state);
+        self.at(&declaration.name);
+        let constant = self.make_constant(Value::object(function));
+        self.emit_bytes(OpCode::Closure, constant);
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+    }
+
+    fn finish(&mut self, state: FunctionState) -> ObjRef {
+        let function = Function {
+            name: state.name,
+            arity: state.arity,
+            upvalue_count: state.upvalues.len(),
+            chunk: Rc::new(state.chunk),
+        };
+        return self.heap.alloc(Object::Function(function));
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Literal(token) => {
+                self.at(token);
+                match &token._type {
+                    TokenType::Nil => self.emit_op(OpCode::Nil),
+                    TokenType::True => self.emit_op(OpCode::True),
+                    TokenType::False => self.emit_op(OpCode::False),
+                    TokenType::Number(n) => self.emit_constant(Value::number(*n)),
+                    TokenType::StringLiteral(s) => {
+                        let string = self.heap.intern_symbol(s.clone());
+                        self.emit_constant(Value::object(string))
+                    }
+                    _ => unreachable!("the parser only builds literals from literal tokens"),
+                }
+            }
+            Expression::Grouping(expression) => self.expression(expression),
+            Expression::Unary(operator, value) => {
+                self.expression(value);
+                self.at_span(operator, expression.span());
+                match operator._type {
+                    TokenType::Minus => self.emit_op(OpCode::Negate),
+                    TokenType::Bang => self.emit_op(OpCode::Not),
+                    _ => unreachable!("the parser only builds unary '-' and '!'"),
+                }
+            }
+            Expression::Binary(lhs, operator, rhs) => {
+                self.expression(lhs);
+                self.expression(rhs);
+                self.at_span(operator, expression.span());
+                match operator._type {
+                    TokenType::BangEqual => {
+                        self.emit_op(OpCode::Equal);
+                        self.emit_op(OpCode::Not);
+                    }
+                    TokenType::EqualEqual => self.emit_op(OpCode::Equal),
+                    TokenType::Greater => self.emit_op(OpCode::Greater),
+                    TokenType::GreaterEqual => self.emit_op(OpCode::GreaterEqual),
+                    TokenType::Less => self.emit_op(OpCode::Less),
+                    TokenType::LessEqual => self.emit_op(OpCode::LessEqual),
+                    TokenType::Plus => self.emit_op(OpCode::Add),
+                    TokenType::Minus => self.emit_op(OpCode::Subtract),
+                    TokenType::Star => self.emit_op(OpCode::Multiply),
+                    TokenType::Slash => self.emit_op(OpCode::Divide),
+                    _ => unreachable!("the parser only builds binary arithmetic and comparisons"),
+                }
+            }
+            Expression::Variable(_, name) => {
+                self.at(name);
+                self.named_variable(&name.lexeme, false);
+            }
+            Expression::Assign(_, name, value) => {
+                self.expression(value);
+                self.at(name);
+                self.named_variable(&name.lexeme, true);
+            }
+            Expression::Logical(lhs, operator, rhs) => {
+                self.expression(lhs);
+                self.at(operator);
+                if matches!(operator._type, TokenType::Or) {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(rhs);
+                    self.patch_jump(end_jump);
+                } else {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(rhs);
+                    self.patch_jump(end_jump);
+                }
+            }
+            Expression::Call(callee, paren, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                self.at_span(paren, expression.span());
+                self.emit_bytes(OpCode::Call, arguments.len() as u8);
+            }
+            Expression::Get(object, name) => {
+                self.expression(object);
+                self.at(name);
+                let name = self.identifier_constant(&name.lexeme);
+                self.emit_bytes(OpCode::GetProperty, name);
+            }
+            Expression::Set(object, name, value) => {
+                self.expression(object);
+                self.expression(value);
+                self.at(name);
+                let name = self.identifier_constant(&name.lexeme);
+                self.emit_bytes(OpCode::SetProperty, name);
+            }
+            Expression::This(_, keyword) => {
+                self.at(keyword);
+                self.named_variable("this", false);
+            }
+            Expression::Super(_, keyword, method) => {
+                self.at(keyword);
+                self.named_variable("this", false);
+                self.named_variable("super", false);
+                self.at(method);
+                let name = self.identifier_constant(&method.lexeme);
+                self.emit_bytes(OpCode::GetSuper, name);
+            }
+        }
+    }
+
+    fn named_variable(&mut self, name: &str, assign: bool) {
+        let top = self.states.len() - 1;
+        let (get, set, operand) = if let Some(slot) = self.resolve_local(top, name) {
+            (OpCode::GetLocal, OpCode::SetLocal, slot)
+        } else if let Some(index) = self.resolve_upvalue(top, name) {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index)
+        } else {
+            let constant = self.identifier_constant(name);
+            (OpCode::GetGlobal, OpCode::SetGlobal, constant)
+        };
+        self.emit_bytes(if assign { set } else { get }, operand);
+    }
+
+    fn define_variable(&mut self, name: &Token) {
+        if self.state().scope_depth > 0 {
+            // The initializer's value is already sitting in the new slot.
+            self.add_local(&name.lexeme);
+            return;
+        }
+        let constant = self.identifier_constant(&name.lexeme);
+        self.emit_bytes(OpCode::DefineGlobal, constant);
+    }
+
+    fn add_local(&mut self, name: &str) {
+        if self.state().locals.len() == MAX_LOCALS {
+            self.error("Too many local variables in function.");
+            return;
+        }
+        let depth = self.state().scope_depth;
+        self.state_mut().locals.push(Local {
+            name: name.to_string(),
+            depth,
+            is_captured: false,
+        });
+    }
+
+    fn resolve_local(&self, state: usize, name: &str) -> Option<u8> {
+        return self.states[state]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8);
+    }
+
+    fn resolve_upvalue(&mut self, state: usize, name: &str) -> Option<u8> {
+        if state == 0 {
+            return None;
+        }
+        if let Some(slot) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(state, slot, true));
+        }
+        if let Some(index) = self.resolve_upvalue(state - 1, name) {
+            return Some(self.add_upvalue(state, index, false));
+        }
+        return None;
+    }
+
+    fn add_upvalue(&mut self, state: usize, index: u8, is_local: bool) -> u8 {
+        let upvalue = UpvalueIndex { index, is_local };
+        let upvalues = &self.states[state].upvalues;
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return existing as u8;
+        }
+        if upvalues.len() == MAX_UPVALUES {
+            self.error("Too many closure variables in function.");
+            return 0;
+        }
+        self.states[state].upvalues.push(upvalue);
+        return (self.states[state].upvalues.len() - 1) as u8;
+    }
+
+    fn begin_scope(&mut self) {
+        self.state_mut().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.state_mut().scope_depth -= 1;
+        let depth = self.state().scope_depth;
+        while let Some(local) = self.state().locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            if local.is_captured {
+                self.emit_op(OpCode::CloseUpvalue);
+            } else {
+                self.emit_op(OpCode::Pop);
+            }
+            self.state_mut().locals.pop();
+        }
+    }
+
+    fn at(&mut self, token: &Token) {
+        self.at_span(token, token.span);
+    }
+
+    /// Like `at`, but runtime errors underline `span` rather than just the
+    /// token.
+    fn at_span(&mut self, token: &Token, span: Span) {
+        self.location = Location::new(token, span);
+        self.token = token.clone();
+    }
+
+    /// Points at a variable expression, which is the only thing that can
+    /// appear where a superclass is expected.
+    fn at_expression(&mut self, expression: &Expression) {
+        if let Expression::Variable(_, name) = expression {
+            self.at(name);
+        }
+    }
+
+    fn state(&self) -> &FunctionState {
+        return self.states.last().unwrap();
+    }
+
+    fn state_mut(&mut self) -> &mut FunctionState {
+        return self.states.last_mut().unwrap();
+    }
+
+    fn chunk_len(&self) -> usize {
+        return self.state().chunk.code.len();
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let location = self.location;
+        self.state_mut().chunk.write(byte, location);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_bytes(&mut self, op: OpCode, operand: u8) {
+        self.emit_op(op);
+        self.emit_byte(operand);
+    }
+
+    fn emit_return(&mut self) {
+        if self.state().kind == FunctionKind::Initializer {
+            self.emit_bytes(OpCode::GetLocal, 0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.emit_op(OpCode::Return);
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        return self.chunk_len() - 2;
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.chunk_len() - offset - 2;
+        if jump > u16::MAX as usize {
+            self.error("Too much code to jump over.");
+        }
+        let code = &mut self.state_mut().chunk.code;
+        code[offset] = (jump >> 8) as u8;
+        code[offset + 1] = jump as u8;
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_op(OpCode::Loop);
+        let offset = self.chunk_len() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            self.error("Loop body too large.");
+        }
+        self.emit_byte((offset >> 8) as u8);
+        self.emit_byte(offset as u8);
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let constant = self.make_constant(value);
+        self.emit_bytes(OpCode::Constant, constant);
+    }
+
+    fn make_constant(&mut self, value: Value) -> u8 {
+        if self.state().panic_mode {
+            return 0;
+        }
+        if self.state().chunk.constants.len() > u8::MAX as usize {
+            self.error("Too many constants in one chunk.");
+            return 0;
+        }
+        return self.state_mut().chunk.add_constant(value) as u8;
+    }
+
+    fn identifier_constant(&mut self, name: &str) -> u8 {
+        let name = Value::object(self.heap.intern(name));
+        let existing = self.state().chunk.constants.iter().position(|c| *c == name);
+        match existing {
+            Some(constant) if constant <= u8::MAX as usize => return constant as u8,
+            _ => return self.make_constant(name),
+        }
+    }
+
+    fn error(&mut self, message: &str) {
+        if self.state().panic_mode {
+            return;
+        }
+        self.state_mut().panic_mode = true;
+        self.errors.push(ParseError::Compile {
+            token: self.token.clone(),
+            message: message.to_string(),
+        });
+    }
+}
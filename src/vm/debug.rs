@@ -0,0 +1,170 @@
+use std::fmt::Write;
+
+use super::chunk::{Chunk, OpCode};
+use super::heap::{Heap, ObjRef};
+use super::value::{Object, Printer};
+
+/// Disassembles `function` followed by every function nested in it, in the
+/// order they appear in the constant pools.
+pub fn disassemble_function(heap: &Heap, function: ObjRef) -> String {
+    let mut output = String::new();
+    let function = heap.function(function);
+    disassemble_chunk(heap, &function.chunk, &function.to_string(), &mut output);
+    for constant in &function.chunk.constants {
+        if let Some(nested) = constant.as_object() {
+            if let Object::Function(_) = heap.get(nested) {
+                output.push('\n');
+                output += &disassemble_function(heap, nested);
+            }
+        }
+    }
+    return output;
+}
+
+fn disassemble_chunk(heap: &Heap, chunk: &Chunk, name: &str, output: &mut String) {
+    writeln!(output, "== {} ==", name).unwrap();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(heap, chunk, offset, output);
+    }
+}
+
+/// Writes the instruction at `offset` and returns the offset of the next one.
+fn disassemble_instruction(
+    heap: &Heap,
+    chunk: &Chunk,
+    offset: usize,
+    output: &mut String,
+) -> usize {
+    write!(output, "{:04} ", offset).unwrap();
+    let line = chunk.locations[offset].line;
+    if offset > 0 && line == chunk.locations[offset - 1].line {
+        write!(output, "   | ").unwrap();
+    } else {
+        write!(output, "{:4} ", line).unwrap();
+    }
+
+    let Ok(op) = OpCode::try_from(chunk.code[offset]) else {
+        writeln!(output, "Unknown opcode {}", chunk.code[offset]).unwrap();
+        return offset + 1;
+    };
+    let name = op.to_string();
+
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method => {
+            let constant = chunk.code[offset + 1];
+            let value = chunk.constants[constant as usize];
+            let value = Printer { heap, value };
+            writeln!(output, "{:<16} {:4} '{}'", name, constant, value).unwrap();
+            return offset + 2;
+        }
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call => {
+            let slot = chunk.code[offset + 1];
+            writeln!(output, "{:<16} {:4}", name, slot).unwrap();
+            return offset + 2;
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+            let jump = (chunk.code[offset + 1] as usize) << 8 | chunk.code[offset + 2] as usize;
+            let target = match op {
+                OpCode::Loop => offset + 3 - jump,
+                _ => offset + 3 + jump,
+            };
+            writeln!(output, "{:<16} {:4} -> {}", name, offset, target).unwrap();
+            return offset + 3;
+        }
+        OpCode::Closure => {
+            let constant = chunk.code[offset + 1];
+            let Some(function) = chunk.constants[constant as usize].as_object() else {
+                unreachable!("closures are only built from function constants");
+            };
+            let function = heap.function(function);
+            writeln!(output, "{:<16} {:4} {}", name, constant, function).unwrap();
+
+            let mut offset = offset + 2;
+            for _ in 0..function.upvalue_count {
+                let kind = match chunk.code[offset] {
+                    1 => "local",
+                    _ => "upvalue",
+                };
+                let index = chunk.code[offset + 1];
+                writeln!(
+                    output,
+                    "{:04}      |                     {} {}",
+                    offset, kind, index
+                )
+                .unwrap();
+                offset += 2;
+            }
+            return offset;
+        }
+        _ => {
+            writeln!(output, "{}", name).unwrap();
+            return offset + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::disassemble;
+
+    #[test]
+    fn disassembles_in_the_style_of_clox() {
+        let source = "\
+fun outer() {
+  var x = 1;
+  fun inner() {
+    return x;
+  }
+  return inner;
+}
+if (true and outer()) print \"yes\";
+";
+        let expected = "\
+== <script> ==
+0000    1 OP_CLOSURE          0 <fn outer>
+0002    | OP_DEFINE_GLOBAL    1 'outer'
+0004    8 OP_TRUE
+0005    | OP_JUMP_IF_FALSE    5 -> 13
+0008    | OP_POP
+0009    | OP_GET_GLOBAL       1 'outer'
+0011    | OP_CALL             0
+0013    | OP_JUMP_IF_FALSE   13 -> 23
+0016    | OP_POP
+0017    | OP_CONSTANT         2 'yes'
+0019    | OP_PRINT
+0020    | OP_JUMP            20 -> 24
+0023    | OP_POP
+0024    | OP_NIL
+0025    | OP_RETURN
+
+== <fn outer> ==
+0000    2 OP_CONSTANT         0 '1'
+0002    3 OP_CLOSURE          1 <fn inner>
+0004      |                     local 1
+0006    6 OP_GET_LOCAL        2
+0008    | OP_RETURN
+0009    | OP_NIL
+0010    | OP_RETURN
+
+== <fn inner> ==
+0000    4 OP_GET_UPVALUE      0
+0002    | OP_RETURN
+0003    | OP_NIL
+0004    | OP_RETURN
+";
+        assert_eq!(disassemble(source, false).unwrap(), expected);
+    }
+}
@@ -0,0 +1,324 @@
+use std::mem;
+
+use super::chunk::Location;
+use super::value::{BoundMethod, Class, Closure, Function, Instance, Object, Upvalue, Value};
+use crate::intern::Symbol;
+use crate::table::Table;
+
+/// How much the heap may grow before the first collection.
+const INITIAL_THRESHOLD: usize = 1024 * 1024;
+/// After a collection the next one happens once the surviving bytes have
+/// grown by this factor.
+const GROWTH_FACTOR: usize = 2;
+
+/// A handle to an object in the [`Heap`]. Handles stay valid for as long as
+/// the object is reachable from the VM's roots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjRef(pub(super) usize);
+
+struct Entry {
+    marked: bool,
+    size: usize,
+    object: Object,
+}
+
+/// Owns every object the VM allocates and frees the unreachable ones with a
+/// mark-and-sweep collector. Marking starts from roots the VM hands in and
+/// proceeds through a gray worklist so deep object graphs don't recurse.
+pub struct Heap {
+    entries: Vec<Option<Entry>>,
+    free: Vec<usize>,
+    /// Every live string, so equal strings share one object. Entries don't
+    /// keep their string alive; they are dropped when it is swept.
+    strings: Table<ObjRef>,
+    gray: Vec<ObjRef>,
+    bytes_allocated: usize,
+    next_gc: usize,
+    stress: bool,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Heap {
+            entries: Vec::new(),
+            free: Vec::new(),
+            strings: Table::new(),
+            gray: Vec::new(),
+            bytes_allocated: 0,
+            next_gc: INITIAL_THRESHOLD,
+            stress: false,
+        }
+    }
+
+    /// Makes [`Heap::should_collect`] always true, so every allocation runs
+    /// a full collection and dangling handles show up immediately.
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    pub fn should_collect(&self) -> bool {
+        return self.stress || self.bytes_allocated > self.next_gc;
+    }
+
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        let size = size_of(&object);
+        self.bytes_allocated += size;
+        let entry = Entry {
+            marked: false,
+            size,
+            object,
+        };
+        match self.free.pop() {
+            Some(index) => {
+                self.entries[index] = Some(entry);
+                return ObjRef(index);
+            }
+            None => {
+                self.entries.push(Some(entry));
+                return ObjRef(self.entries.len() - 1);
+            }
+        }
+    }
+
+    /// Returns the string object holding `string`, allocating one only if no
+    /// equal string is live.
+    pub fn intern(&mut self, string: &str) -> ObjRef {
+        return self.intern_symbol(Symbol::new(string));
+    }
+
+    /// Like [`Heap::intern`] for text that is already a symbol, such as an
+    /// identifier or a string literal.
+    pub fn intern_symbol(&mut self, symbol: Symbol) -> ObjRef {
+        if let Some(&existing) = self.strings.get(&symbol) {
+            return existing;
+        }
+        let reference = self.alloc(Object::String(symbol.clone()));
+        self.strings.insert(symbol, reference);
+        return reference;
+    }
+
+    pub fn get(&self, reference: ObjRef) -> &Object {
+        return &self.entries[reference.0]
+            .as_ref()
+            .expect("handle to a freed object")
+            .object;
+    }
+
+    pub fn get_mut(&mut self, reference: ObjRef) -> &mut Object {
+        return &mut self.entries[reference.0]
+            .as_mut()
+            .expect("handle to a freed object")
+            .object;
+    }
+
+    pub fn string(&self, reference: ObjRef) -> &Symbol {
+        let Object::String(string) = self.get(reference) else {
+            unreachable!("expected a string object");
+        };
+        return string;
+    }
+
+    pub fn function(&self, reference: ObjRef) -> &Function {
+        let Object::Function(function) = self.get(reference) else {
+            unreachable!("expected a function object");
+        };
+        return function;
+    }
+
+    pub fn closure(&self, reference: ObjRef) -> &Closure {
+        let Object::Closure(closure) = self.get(reference) else {
+            unreachable!("expected a closure object");
+        };
+        return closure;
+    }
+
+    pub fn upvalue(&self, reference: ObjRef) -> &Upvalue {
+        let Object::Upvalue(upvalue) = self.get(reference) else {
+            unreachable!("expected an upvalue object");
+        };
+        return upvalue;
+    }
+
+    pub fn upvalue_mut(&mut self, reference: ObjRef) -> &mut Upvalue {
+        let Object::Upvalue(upvalue) = self.get_mut(reference) else {
+            unreachable!("expected an upvalue object");
+        };
+        return upvalue;
+    }
+
+    pub fn class(&self, reference: ObjRef) -> &Class {
+        let Object::Class(class) = self.get(reference) else {
+            unreachable!("expected a class object");
+        };
+        return class;
+    }
+
+    pub fn class_mut(&mut self, reference: ObjRef) -> &mut Class {
+        let Object::Class(class) = self.get_mut(reference) else {
+            unreachable!("expected a class object");
+        };
+        return class;
+    }
+
+    pub fn instance_mut(&mut self, reference: ObjRef) -> &mut Instance {
+        let Object::Instance(instance) = self.get_mut(reference) else {
+            unreachable!("expected an instance object");
+        };
+        return instance;
+    }
+
+    pub fn mark_value(&mut self, value: Value) {
+        if let Some(reference) = value.as_object() {
+            self.mark_object(reference);
+        }
+    }
+
+    pub fn mark_object(&mut self, reference: ObjRef) {
+        self.gray.push(reference);
+    }
+
+    /// Frees everything not reachable from the objects marked since the last
+    /// collection.
+    pub fn collect(&mut self) {
+        self.trace_references();
+
+        let entries = &self.entries;
+        self.strings
+            .retain(|_, reference| entries[reference.0].as_ref().is_some_and(|e| e.marked));
+
+        self.sweep();
+        self.next_gc = (self.bytes_allocated * GROWTH_FACTOR).max(INITIAL_THRESHOLD);
+    }
+
+    fn trace_references(&mut self) {
+        while let Some(reference) = self.gray.pop() {
+            let entry = self.entries[reference.0]
+                .as_mut()
+                .expect("handle to a freed object");
+            if entry.marked {
+                continue;
+            }
+            entry.marked = true;
+
+            let gray = &mut self.gray;
+            match &entry.object {
+                Object::String(_) | Object::Native(_) => {}
+                Object::Function(function) => {
+                    gray.extend(
+                        function
+                            .chunk
+                            .constants
+                            .iter()
+                            .filter_map(|value| value.as_object()),
+                    );
+                }
+                Object::Closure(Closure { function, upvalues }) => {
+                    gray.push(*function);
+                    gray.extend(upvalues);
+                }
+                Object::Upvalue(Upvalue::Open(_)) => {}
+                Object::Upvalue(Upvalue::Closed(value)) => gray.extend(value.as_object()),
+                Object::Class(class) => gray.extend(class.methods.values()),
+                Object::Instance(Instance { class, fields }) => {
+                    gray.push(*class);
+                    gray.extend(fields.values().filter_map(|value| value.as_object()));
+                }
+                Object::BoundMethod(BoundMethod { receiver, method }) => {
+                    gray.push(*method);
+                    gray.extend(receiver.as_object());
+                }
+            }
+        }
+    }
+
+    fn sweep(&mut self) {
+        for (index, slot) in self.entries.iter_mut().enumerate() {
+            let Some(entry) = slot else {
+                continue;
+            };
+            if entry.marked {
+                entry.marked = false;
+                continue;
+            }
+            self.bytes_allocated -= entry.size;
+            *slot = None;
+            self.free.push(index);
+        }
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap::new()
+    }
+}
+
+/// An estimate of the memory `object` owns when allocated, used to pace
+/// collections.
+fn size_of(object: &Object) -> usize {
+    let owned = match object {
+        Object::String(string) => string.len(),
+        Object::Function(function) => {
+            function.chunk.code.len() * (1 + mem::size_of::<Location>())
+                + function.chunk.constants.len() * mem::size_of::<Value>()
+        }
+        Object::Closure(closure) => closure.upvalues.len() * mem::size_of::<ObjRef>(),
+        Object::Class(class) => class.methods.len() * mem::size_of::<(Symbol, ObjRef)>(),
+        Object::Instance(instance) => instance.fields.len() * mem::size_of::<(Symbol, Value)>(),
+        Object::Native(_) | Object::Upvalue(_) | Object::BoundMethod(_) => 0,
+    };
+    return mem::size_of::<Entry>() + owned;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn live_objects(heap: &Heap) -> usize {
+        return heap.entries.iter().flatten().count();
+    }
+
+    #[test]
+    fn collect_frees_unreachable_objects() {
+        let mut heap = Heap::new();
+        let kept = heap.intern("kept");
+        let dropped = heap.intern("dropped");
+        let upvalue = heap.alloc(Object::Upvalue(Upvalue::Closed(Value::object(kept))));
+        assert_eq!(live_objects(&heap), 3);
+
+        heap.mark_object(upvalue);
+        heap.collect();
+        assert_eq!(live_objects(&heap), 2);
+        assert!(heap.entries[dropped.0].is_none());
+        assert_eq!(heap.string(kept).to_string(), "kept");
+
+        heap.collect();
+        assert_eq!(live_objects(&heap), 0);
+        assert_eq!(heap.bytes_allocated, 0);
+    }
+
+    #[test]
+    fn collect_forgets_interned_strings_it_frees() {
+        let mut heap = Heap::new();
+        let first = heap.intern("text");
+        assert_eq!(heap.intern("text"), first);
+
+        heap.collect();
+        assert!(heap.strings.get(&Symbol::new("text")).is_none());
+
+        heap.intern("other");
+        let second = heap.intern("text");
+        assert_eq!(live_objects(&heap), 2);
+        assert_eq!(heap.string(second).to_string(), "text");
+    }
+
+    #[test]
+    fn freed_slots_are_reused() {
+        let mut heap = Heap::new();
+        let first = heap.intern("first");
+        heap.collect();
+        let second = heap.intern("second");
+        assert_eq!(first, second);
+        assert_eq!(heap.entries.len(), 1);
+    }
+}
@@ -0,0 +1,368 @@
+use std::fmt;
+use std::rc::Rc;
+
+use super::chunk::Chunk;
+use super::heap::{Heap, ObjRef};
+use crate::intern::Symbol;
+use crate::interpreter::NativeFn;
+use crate::table::Table;
+
+/// A value on the VM's stack or in a constant table: nil, a boolean, a
+/// number or a handle to a heap object. Strings are interned, so comparing
+/// handles compares contents.
+///
+/// By default this is a tagged enum. With the `nan-boxing` feature it is a
+/// single `u64` instead; both representations have the same constructors
+/// and accessors, so the rest of the VM doesn't depend on which one is
+/// built.
+#[cfg(not(feature = "nan-boxing"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Object(ObjRef),
+}
+
+#[cfg(not(feature = "nan-boxing"))]
+impl Value {
+    pub const NIL: Value = Value::Nil;
+
+    pub fn boolean(b: bool) -> Value {
+        return Value::Boolean(b);
+    }
+
+    pub fn number(n: f64) -> Value {
+        return Value::Number(n);
+    }
+
+    pub fn object(reference: ObjRef) -> Value {
+        return Value::Object(reference);
+    }
+
+    pub fn as_boolean(self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => return Some(b),
+            _ => return None,
+        }
+    }
+
+    pub fn as_number(self) -> Option<f64> {
+        match self {
+            Value::Number(n) => return Some(n),
+            _ => return None,
+        }
+    }
+
+    pub fn as_object(self) -> Option<ObjRef> {
+        match self {
+            Value::Object(reference) => return Some(reference),
+            _ => return None,
+        }
+    }
+
+    pub fn is_falsey(self) -> bool {
+        return matches!(self, Value::Nil | Value::Boolean(false));
+    }
+}
+
+/// A NaN-boxed value. Any `u64` whose quiet-NaN bits aren't all set is a
+/// double. The rest of that space encodes everything else: with the sign bit
+/// set, the low bits are an object handle; without it, they tag nil, false
+/// or true.
+#[cfg(feature = "nan-boxing")]
+#[derive(Clone, Copy)]
+pub struct Value(u64);
+
+#[cfg(feature = "nan-boxing")]
+const _: () = assert!(std::mem::size_of::<Value>() == 8);
+
+#[cfg(feature = "nan-boxing")]
+impl Value {
+    const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+    const QNAN: u64 = 0x7ffc_0000_0000_0000;
+    const OBJECT: u64 = Value::SIGN_BIT | Value::QNAN;
+
+    pub const NIL: Value = Value(Value::QNAN | 1);
+    const FALSE: Value = Value(Value::QNAN | 2);
+    const TRUE: Value = Value(Value::QNAN | 3);
+
+    pub fn boolean(b: bool) -> Value {
+        return if b { Value::TRUE } else { Value::FALSE };
+    }
+
+    pub fn number(n: f64) -> Value {
+        // Arithmetic can produce NaNs with arbitrary payloads; collapse them
+        // to the canonical one so none of them reads back as a tag.
+        if n.is_nan() {
+            return Value(f64::NAN.to_bits());
+        }
+        return Value(n.to_bits());
+    }
+
+    pub fn object(reference: ObjRef) -> Value {
+        return Value(Value::OBJECT | reference.0 as u64);
+    }
+
+    pub fn as_boolean(self) -> Option<bool> {
+        match self.0 {
+            bits if bits == Value::TRUE.0 => return Some(true),
+            bits if bits == Value::FALSE.0 => return Some(false),
+            _ => return None,
+        }
+    }
+
+    pub fn as_number(self) -> Option<f64> {
+        if self.0 & Value::QNAN == Value::QNAN {
+            return None;
+        }
+        return Some(f64::from_bits(self.0));
+    }
+
+    pub fn as_object(self) -> Option<ObjRef> {
+        if self.0 & Value::OBJECT != Value::OBJECT {
+            return None;
+        }
+        return Some(ObjRef((self.0 & !Value::OBJECT) as usize));
+    }
+
+    pub fn is_falsey(self) -> bool {
+        return self.0 == Value::NIL.0 || self.0 == Value::FALSE.0;
+    }
+}
+
+#[cfg(feature = "nan-boxing")]
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        // Numbers compare as doubles so that NaN is unequal to itself, the
+        // same as with the enum representation.
+        match (self.as_number(), other.as_number()) {
+            (Some(n), Some(m)) => return n == m,
+            _ => return self.0 == other.0,
+        }
+    }
+}
+
+#[cfg(feature = "nan-boxing")]
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(n) = self.as_number() {
+            return write!(f, "Number({:?})", n);
+        }
+        if let Some(b) = self.as_boolean() {
+            return write!(f, "Boolean({})", b);
+        }
+        match self.as_object() {
+            Some(reference) => write!(f, "Object({:?})", reference),
+            None => write!(f, "Nil"),
+        }
+    }
+}
+
+/// A heap-allocated object. Everything a `Value` can point at lives in the
+/// [`Heap`] and is reclaimed by its collector.
+pub enum Object {
+    String(Symbol),
+    Function(Function),
+    Native(Native),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+}
+
+/// A compiled function body. The top-level script is a function with an
+/// empty name.
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    /// Shared with the call frames executing it, which read it on every
+    /// instruction.
+    pub chunk: Rc<Chunk>,
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name.as_str() {
+            "" => write!(f, "<script>"),
+            name => write!(f, "<fn {}>", name),
+        }
+    }
+}
+
+/// A host function. It takes and returns the tree-walker's
+/// [`Object`](crate::Object)s, so the same natives work on both backends.
+pub struct Native {
+    pub arity: usize,
+    pub function: Rc<NativeFn>,
+}
+
+/// A captured variable: it points into the stack while the variable's scope
+/// is live and owns the value once the scope has ended.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Closure {
+    pub function: ObjRef,
+    pub upvalues: Vec<ObjRef>,
+}
+
+pub struct Class {
+    pub name: Symbol,
+    pub methods: Table<ObjRef>,
+}
+
+pub struct Instance {
+    pub class: ObjRef,
+    pub fields: Table<Value>,
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
+
+/// Formats a value the way `print` shows it, following handles into the
+/// heap.
+pub struct Printer<'a> {
+    pub heap: &'a Heap,
+    pub value: Value,
+}
+
+impl fmt::Display for Printer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let heap = self.heap;
+        if let Some(n) = self.value.as_number() {
+            return write!(f, "{}", n);
+        }
+        if let Some(b) = self.value.as_boolean() {
+            return write!(f, "{}", b);
+        }
+        let Some(object) = self.value.as_object() else {
+            return write!(f, "nil");
+        };
+        match heap.get(object) {
+            Object::String(s) => write!(f, "{}", s),
+            Object::Function(function) => write!(f, "{}", function),
+            Object::Native(_) => write!(f, "<native fn>"),
+            Object::Closure(closure) => write!(f, "{}", heap.function(closure.function)),
+            Object::Upvalue(_) => write!(f, "upvalue"),
+            Object::Class(class) => write!(f, "{}", class.name),
+            Object::Instance(instance) => {
+                write!(f, "{} instance", heap.class(instance.class).name)
+            }
+            Object::BoundMethod(bound) => {
+                let closure = heap.closure(bound.method);
+                write!(f, "{}", heap.function(closure.function))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_nil(value: Value) -> bool {
+        return value.as_boolean().is_none()
+            && value.as_number().is_none()
+            && value.as_object().is_none();
+    }
+
+    #[test]
+    fn nil_round_trips() {
+        assert!(is_nil(Value::NIL));
+        assert!(Value::NIL.is_falsey());
+        assert_eq!(Value::NIL, Value::NIL);
+        assert_ne!(Value::NIL, Value::boolean(false));
+        assert_ne!(Value::NIL, Value::number(0.0));
+    }
+
+    #[test]
+    fn booleans_round_trip() {
+        for b in [false, true] {
+            let value = Value::boolean(b);
+            assert_eq!(value.as_boolean(), Some(b));
+            assert_eq!(value.as_number(), None);
+            assert_eq!(value.as_object(), None);
+            assert_eq!(value.is_falsey(), !b);
+        }
+        assert_ne!(Value::boolean(true), Value::boolean(false));
+    }
+
+    #[test]
+    fn numbers_round_trip() {
+        let numbers = [
+            0.0,
+            1.0,
+            -1.5,
+            f64::MAX,
+            f64::MIN,
+            f64::MIN_POSITIVE,
+            f64::EPSILON,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ];
+        for n in numbers {
+            let value = Value::number(n);
+            assert_eq!(value.as_number().map(f64::to_bits), Some(n.to_bits()));
+            assert_eq!(value.as_boolean(), None);
+            assert_eq!(value.as_object(), None);
+            assert!(!value.is_falsey());
+            assert_eq!(value, Value::number(n));
+        }
+    }
+
+    #[test]
+    fn zeros_keep_their_sign_but_compare_equal() {
+        let positive = Value::number(0.0).as_number().unwrap();
+        let negative = Value::number(-0.0).as_number().unwrap();
+        assert!(positive.is_sign_positive());
+        assert!(negative.is_sign_negative());
+        assert_eq!(Value::number(0.0), Value::number(-0.0));
+    }
+
+    #[test]
+    fn nan_is_unequal_to_itself() {
+        let nan = Value::number(f64::NAN);
+        assert!(nan.as_number().unwrap().is_nan());
+        assert_ne!(nan, nan);
+        assert_ne!(nan, Value::number(f64::NAN));
+    }
+
+    #[test]
+    fn nans_with_payloads_stay_numbers() {
+        // Bit patterns that would read back as nil, true or an object if
+        // they were stored unchanged.
+        let payloads = [
+            0x7ffc_0000_0000_0001,
+            0x7ffc_0000_0000_0003,
+            0xfffc_0000_0000_0005,
+            0xffff_ffff_ffff_ffff,
+        ];
+        for bits in payloads {
+            let value = Value::number(f64::from_bits(bits));
+            assert!(value.as_number().unwrap().is_nan());
+            assert_eq!(value.as_boolean(), None);
+            assert_eq!(value.as_object(), None);
+            assert!(!value.is_falsey());
+        }
+    }
+
+    #[test]
+    fn objects_round_trip() {
+        for index in [0, 1, 42, u32::MAX as usize] {
+            let value = Value::object(ObjRef(index));
+            assert_eq!(value.as_object(), Some(ObjRef(index)));
+            assert_eq!(value.as_number(), None);
+            assert_eq!(value.as_boolean(), None);
+            assert!(!value.is_falsey());
+            assert_eq!(value, Value::object(ObjRef(index)));
+        }
+        assert_ne!(Value::object(ObjRef(1)), Value::object(ObjRef(2)));
+    }
+}
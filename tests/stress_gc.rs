@@ -0,0 +1,184 @@
+#![allow(clippy::needless_return)]
+//! Runs programs on the VM with a collection before every allocation and
+//! checks they print what the tree-walker prints, so an object the VM forgot
+//! to root shows up as a crash or wrong output.
+
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+
+const CLOSURES: &str = r#"
+fun makeCounter() {
+  var count = 0;
+  fun increment() {
+    count = count + 1;
+    return count;
+  }
+  return increment;
+}
+
+var first = makeCounter();
+var second = makeCounter();
+print first();
+print first();
+print second();
+
+var closures = nil;
+for (var i = 0; i < 3; i = i + 1) {
+  var captured = "value " + "of " + "i";
+  fun show() {
+    print captured;
+  }
+  closures = show;
+}
+closures();
+
+fun outer() {
+  var a = "a";
+  fun middle() {
+    var b = "b";
+    fun inner() {
+      return a + b + "c";
+    }
+    return inner;
+  }
+  return middle();
+}
+print outer()();
+"#;
+
+const CLASSES: &str = r#"
+class Shape {
+  init(name) {
+    this.name = name;
+  }
+
+  describe() {
+    return this.name + " with area " + this.area();
+  }
+
+  area() {
+    return "unknown";
+  }
+}
+
+class Square < Shape {
+  init(side) {
+    super.init("square");
+    this.side = side;
+  }
+
+  area() {
+    return "side squared";
+  }
+
+  describe() {
+    return "a " + super.describe();
+  }
+}
+
+var shapes = nil;
+for (var i = 0; i < 5; i = i + 1) {
+  shapes = Square(i);
+}
+print shapes.describe();
+print shapes.side;
+
+var method = Shape("circle").describe;
+print method();
+
+class Node {
+  init(value, next) {
+    this.value = value;
+    this.next = next;
+  }
+}
+
+var list = nil;
+for (var i = 0; i < 20; i = i + 1) {
+  list = Node(i, list);
+}
+var sum = 0;
+while (list != nil) {
+  sum = sum + list.value;
+  list = list.next;
+}
+print sum;
+"#;
+
+const STRINGS: &str = r#"
+var left = "left";
+var right = "right";
+print (left + right) + (right + left);
+var kept = left + right;
+var other = right + left;
+print kept + other;
+
+var text = "";
+for (var i = 0; i < 30; i = i + 1) {
+  text = text + "ab";
+}
+print text;
+print text == "ab" + "ab" + text + "ab" + "ab";
+
+var a = "con" + "cat";
+var b = "conc" + "at";
+print a == b;
+
+fun repeat(string, times) {
+  var result = "";
+  for (var i = 0; i < times; i = i + 1) {
+    result = result + string;
+  }
+  return result;
+}
+print repeat("xy", 10);
+print repeat("xy", 10) == repeat("x" + "y", 10);
+"#;
+
+fn run(name: &str, source: &str, arguments: &[&str]) -> Output {
+    let path = env::temp_dir().join(format!("lox-stress-gc-{}-{}.lox", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .arg("run")
+        .args(arguments)
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    return output;
+}
+
+fn assert_same_output(name: &str, source: &str) {
+    let tree = run(name, source, &["--backend=tree"]);
+    let vm = run(name, source, &["--backend=vm", "--stress-gc"]);
+    assert!(
+        tree.status.success(),
+        "{}",
+        String::from_utf8_lossy(&tree.stderr)
+    );
+    assert!(
+        vm.status.success(),
+        "{}",
+        String::from_utf8_lossy(&vm.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&vm.stdout),
+        String::from_utf8_lossy(&tree.stdout)
+    );
+}
+
+#[test]
+fn closures_survive_stress_gc() {
+    assert_same_output("closures", CLOSURES);
+}
+
+#[test]
+fn classes_survive_stress_gc() {
+    assert_same_output("classes", CLASSES);
+}
+
+#[test]
+fn string_concatenation_survives_stress_gc() {
+    assert_same_output("strings", STRINGS);
+}